@@ -0,0 +1,157 @@
+//! Hack assembler (project 6): symbol resolution and binary encoding, shared by the
+//! assembler binary and the VM translator.
+
+use std::collections::HashMap;
+
+pub enum InstructionType {
+    A(u16),  // A instruction with a number
+    C { dest: Option<String>, comp: String, jump: Option<String> },
+}
+
+pub fn check_for_symbol_and_parse(instructions: Vec<String>) -> (HashMap<String, u16>, Vec<InstructionType>) {
+    let mut symbol_table: HashMap<String, u16> = HashMap::new();
+    let mut parsed = Vec::new();
+    let mut rom_address = 0u16; 
+    let mut ram_address = 16u16; 
+
+// I spent some time figuring out why my binary output was slightly different from the original, despite the correct execution. Eventually, I discovered that they had hardcoded certain memory spaces.
+// So, I created a table for their values. Before this, I didn't perform this step and simply allocated memory space for the values in sequential order.
+// I guess im a turd or they are idk, but in general this part is not obligatory
+    let predefined_symbols = [
+        ("SP", 0), ("LCL", 1), ("ARG", 2), ("THIS", 3), ("THAT", 4),
+        ("R0", 0), ("R1", 1), ("R2", 2), ("R3", 3), ("R4", 4), ("R5", 5),
+        ("R6", 6), ("R7", 7), ("R8", 8), ("R9", 9), ("R10", 10), ("R11", 11),
+        ("R12", 12), ("R13", 13), ("R14", 14), ("R15", 15), ("SCREEN", 16384),
+        ("KBD", 24576)
+    ];
+    
+    for &(symbol, address) in &predefined_symbols {
+        symbol_table.insert(symbol.to_string(), address);
+    }
+
+    // First pass: handle labels
+    for line in instructions.iter() {
+        let trimmed_line = line.trim();
+        if trimmed_line.starts_with("(") && trimmed_line.ends_with(")") {
+            let symbol = trimmed_line[1..trimmed_line.len() - 1].to_string();
+            symbol_table.insert(symbol, rom_address);
+        } else if !trimmed_line.starts_with("//") && !trimmed_line.is_empty() {
+            rom_address += 1;
+        }
+    }
+
+    // Second pass: handle other instructions
+    for line in instructions.iter() {
+        let trimmed_line = line.trim();
+        if let Some(symbol) = trimmed_line.strip_prefix("@") {
+            let symbol = symbol.to_string();
+            if let Ok(value) = symbol.parse::<u16>() {
+                parsed.push(InstructionType::A(value));
+            } else {
+                if !symbol_table.contains_key(&symbol) {
+                    symbol_table.insert(symbol.clone(), ram_address);
+                    parsed.push(InstructionType::A(ram_address));
+                    ram_address += 1;
+                } else {
+                    parsed.push(InstructionType::A(*symbol_table.get(&symbol).unwrap()));
+                }
+            }
+        } else if !trimmed_line.starts_with("(") && !trimmed_line.starts_with("//") && !trimmed_line.is_empty() {
+            let (dest, comp, jump) = parse_c_instruction(trimmed_line);
+            parsed.push(InstructionType::C { dest, comp, jump });
+        }
+    }
+
+    (symbol_table, parsed)
+}
+
+/// The 7 `a c1..c6` bits of each computation mnemonic, accepting both operand orders
+/// for the commutative ones (`M+D` as well as `D+M`).
+pub fn comp_table() -> HashMap<&'static str, &'static str> {
+    HashMap::from([
+        ("0", "0101010"), ("1", "0111111"), ("-1", "0111010"), ("D", "0001100"),
+        ("A", "0110000"), ("!D", "0001101"), ("!A", "0110001"), ("-D", "0001111"),
+        ("-A", "0110011"), ("D+1", "0011111"), ("A+1", "0110111"), ("D-1", "0001110"),
+        ("A-1", "0110010"), ("D+A", "0000010"), ("D-A", "0010011"), ("A-D", "0000111"),
+        ("D&A", "0000000"), ("D|A", "0010101"), ("M", "1110000"), ("!M", "1110001"),
+        ("-M", "1110011"), ("M+1", "1110111"), ("M-1", "1110010"), ("D+M", "1000010"),
+        ("D-M", "1010011"), ("M-D", "1000111"), ("D&M", "1000000"), ("D|M", "1010101"),
+        ("A+D", "0000010"), ("A&D", "0000000"), ("A|D", "0010101"),
+        ("M+D", "1000010"), ("M&D", "1000000"), ("M|D", "1010101"),
+    ])
+}
+
+/// The 3 destination bits, with `null` for no destination.
+pub fn dest_table() -> HashMap<&'static str, &'static str> {
+    HashMap::from([
+        ("null", "000"), ("M", "001"), ("D", "010"), ("MD", "011"),
+        ("A", "100"), ("AM", "101"), ("AD", "110"), ("AMD", "111"),
+    ])
+}
+
+/// The 3 jump bits, with `null` for no jump.
+pub fn jump_table() -> HashMap<&'static str, &'static str> {
+    HashMap::from([
+        ("null", "000"), ("JGT", "001"), ("JEQ", "010"), ("JGE", "011"),
+        ("JLT", "100"), ("JNE", "101"), ("JLE", "110"), ("JMP", "111"),
+    ])
+}
+
+/// Encodes the instructions as `.hack` text, one 16 character binary line each.
+/// Fails on the first unknown mnemonic.
+pub fn translate_to_binary(instructions: &[InstructionType]) -> Result<String, String> {
+    let comp_table = comp_table();
+    let dest_table = dest_table();
+    let jump_table = jump_table();
+
+    let mut binary_result = String::new();
+    for instruction in instructions {
+        let binary_instruction = match instruction {
+            InstructionType::A(value) => format!("{:016b}", value),
+            InstructionType::C { dest, comp, jump } => {
+                let comp_bits = comp_table.get(comp.as_str())
+                    .ok_or_else(|| format!("Unknown computation: {}", comp))?;
+                let dest_bits = dest_table.get(dest.as_deref().unwrap_or("null"))
+                    .ok_or_else(|| format!("Unknown destination: {}", dest.as_deref().unwrap_or_default()))?;
+                let jump_bits = jump_table.get(jump.as_deref().unwrap_or("null"))
+                    .ok_or_else(|| format!("Unknown jump: {}", jump.as_deref().unwrap_or_default()))?;
+                format!("111{}{}{}", comp_bits, dest_bits, jump_bits)
+            }
+        };
+
+        binary_result.push_str(&binary_instruction);
+        binary_result.push('\n');
+    }
+    Ok(binary_result)
+}
+
+/// Assembles Hack assembly source into `.hack` text.
+pub fn assemble(source: &str) -> Result<String, String> {
+    assemble_with_symbols(source).map(|(binary, _symbol_table)| binary)
+}
+
+/// Assembles Hack assembly source into `.hack` text, also returning the symbol table:
+/// labels with their ROM addresses, predefined symbols and variables with their RAM ones.
+pub fn assemble_with_symbols(source: &str) -> Result<(String, HashMap<String, u16>), String> {
+    let instructions: Vec<String> = source.lines().map(|s| s.to_string()).collect();
+    let (symbol_table, parsed_instructions) = check_for_symbol_and_parse(instructions);
+    Ok((translate_to_binary(&parsed_instructions)?, symbol_table))
+}
+
+fn parse_c_instruction(instruction: &str) -> (Option<String>, String, Option<String>) {
+    let mut dest = None;
+    let mut comp = instruction.to_string();
+    let mut jump = None;
+
+    if let Some(jump_idx) = instruction.find(';') {
+        jump = Some(instruction[jump_idx + 1..].to_string());
+        comp = instruction[..jump_idx].to_string();
+    }
+
+    if let Some(dest_idx) = comp.find('=') {
+        dest = Some(comp[..dest_idx].to_string());
+        comp = comp[dest_idx + 1..].to_string();
+    }
+
+    (dest, comp, jump)
+}
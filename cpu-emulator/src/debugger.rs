@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{disassemble, Computer, RAM_SIZE, ROM_SIZE};
+
+// How far `next` looks ahead for the jump that ends a call sequence.
+const CALL_SEQUENCE_LENGTH: u16 = 80;
+// Instructions `list` shows before and after the position.
+const LIST_CONTEXT: u16 = 5;
+
+/// Names for ROM and RAM addresses, from an assembler symbol table.
+pub struct Symbols {
+    labels: HashMap<String, u16>,
+    variables: HashMap<String, u16>,
+    // Reverse lookups: the label of a ROM address, a VM function's if it has one and
+    // otherwise the alphabetically first, and the name of a RAM address, SP to THAT rather
+    // than R0 to R4.
+    label_names: HashMap<u16, String>,
+    variable_names: HashMap<u16, String>,
+}
+
+impl Symbols {
+    /// The symbols of an assembly program: its labels, variables and the predefined
+    /// symbols.
+    pub fn from_assembly(source: &str) -> Result<Self, String> {
+        let (_binary, symbol_table) = assembler::assemble_with_symbols(source)?;
+        let labels: BTreeSet<&str> = source.lines()
+            .filter_map(|line| line.trim().strip_prefix('(')?.strip_suffix(')'))
+            .collect();
+        let mut symbols = Symbols {
+            labels: HashMap::new(),
+            variables: HashMap::new(),
+            label_names: HashMap::new(),
+            variable_names: HashMap::new(),
+        };
+        let mut symbol_table: Vec<(String, u16)> = symbol_table.into_iter().collect();
+        symbol_table.sort();
+        for (name, address) in symbol_table {
+            if labels.contains(name.as_str()) {
+                let label_name = symbols.label_names.entry(address).or_insert_with(|| name.clone());
+                if is_function_label(&name) && !is_function_label(label_name) {
+                    *label_name = name.clone();
+                }
+                symbols.labels.insert(name, address);
+            } else {
+                if !matches!(name.as_str(), "R0" | "R1" | "R2" | "R3" | "R4") {
+                    symbols.variable_names.entry(address).or_insert_with(|| name.clone());
+                }
+                symbols.variables.insert(name, address);
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Just the predefined symbols, for programs without their assembly source.
+    pub fn predefined() -> Self {
+        Self::from_assembly("").unwrap()
+    }
+
+    // A label or ROM address.
+    fn rom_address(&self, text: &str) -> Option<u16> {
+        match text.parse::<u16>() {
+            Ok(address) => Some(address).filter(|address| (*address as usize) < ROM_SIZE),
+            Err(_) => self.labels.get(text).copied(),
+        }
+    }
+
+    // A variable, predefined symbol, RAM address or `RAM[<address>]`.
+    fn ram_address(&self, text: &str) -> Option<u16> {
+        let text = text.strip_prefix("RAM[").and_then(|text| text.strip_suffix(']')).unwrap_or(text);
+        match text.parse::<u16>() {
+            Ok(address) => Some(address).filter(|address| (*address as usize) < RAM_SIZE),
+            Err(_) => self.variables.get(text).copied(),
+        }
+    }
+
+    // `12 (LOOP)`, or just `12`.
+    fn describe_rom(&self, address: u16) -> String {
+        match self.label_names.get(&address) {
+            Some(label) => format!("{} ({})", address, label),
+            None => address.to_string(),
+        }
+    }
+
+    // `SP (RAM[0])`, or just `RAM[256]`.
+    fn describe_ram(&self, address: u16) -> String {
+        match self.variable_names.get(&address) {
+            Some(name) => format!("{} (RAM[{}])", name, address),
+            None => format!("RAM[{}]", address),
+        }
+    }
+}
+
+/// A command line debugger session: the computer, breakpoints on ROM addresses and
+/// watchpoints on RAM cells. The `help` command lists the others.
+pub struct Debugger {
+    pub computer: Computer,
+    symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+    // The value of each watched cell when last checked.
+    watchpoints: BTreeMap<u16, u16>,
+}
+
+// The commands, as `help` shows them.
+const HELP: &str = "\
+step [n], s       execute n instructions, 1 by default
+next, n           step over a VM call or runtime routine call, otherwise like step
+continue, c       run until a breakpoint, a watchpoint or the halt loop
+break [where], b  stop at a ROM address or label, or list the breakpoints
+delete [where]    remove a breakpoint, or all of them
+watch [what], w   stop when a RAM cell changes, or list the watchpoints
+unwatch [what]    remove a watchpoint, or all of them
+print what, p     show A, D, PC or RAM cells: SP, LCL, a variable, 256 or 256-260
+set what value    change A, D, PC or a RAM cell
+registers, r      show the registers and the VM pointers
+list [where], l   disassemble around PC or a ROM address
+help, h           show this help
+quit, q           leave the debugger";
+
+impl Debugger {
+    pub fn new(computer: Computer, symbols: Symbols) -> Self {
+        Debugger { computer, symbols, breakpoints: BTreeSet::new(), watchpoints: BTreeMap::new() }
+    }
+
+    /// Runs a debugger command, other than `quit`, returning the text to show.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["step" | "s"] => Ok(self.run(1)),
+            ["step" | "s", count] => match count.parse() {
+                Ok(count) if count > 0 => Ok(self.run(count)),
+                _ => Err(format!("Invalid step count: {}", count)),
+            },
+            ["next" | "n"] => Ok(self.next()),
+            ["continue" | "c"] => Ok(self.run(u64::MAX)),
+            ["break" | "b"] => Ok(self.list_breakpoints()),
+            ["break" | "b", at] => {
+                let address = self.rom_address(at)?;
+                self.breakpoints.insert(address);
+                Ok(format!("Breakpoint at {}", self.symbols.describe_rom(address)))
+            },
+            ["delete" | "d"] => {
+                self.breakpoints.clear();
+                Ok("Deleted all breakpoints".to_string())
+            },
+            ["delete" | "d", at] => {
+                let address = self.rom_address(at)?;
+                match self.breakpoints.remove(&address) {
+                    true => Ok(format!("Deleted the breakpoint at {}", self.symbols.describe_rom(address))),
+                    false => Err(format!("No breakpoint at {}", self.symbols.describe_rom(address))),
+                }
+            },
+            ["watch" | "w"] => Ok(self.list_watchpoints()),
+            ["watch" | "w", what] => {
+                let address = self.ram_address(what)?;
+                self.watchpoints.insert(address, self.computer.ram[address as usize]);
+                Ok(format!("Watching {}", self.symbols.describe_ram(address)))
+            },
+            ["unwatch"] => {
+                self.watchpoints.clear();
+                Ok("Deleted all watchpoints".to_string())
+            },
+            ["unwatch", what] => {
+                let address = self.ram_address(what)?;
+                match self.watchpoints.remove(&address) {
+                    Some(_) => Ok(format!("Stopped watching {}", self.symbols.describe_ram(address))),
+                    None => Err(format!("Not watching {}", self.symbols.describe_ram(address))),
+                }
+            },
+            ["print" | "p", what] => self.print(what),
+            ["set", what, value] => self.set(what, value),
+            ["registers" | "r"] => Ok(self.registers()),
+            ["list" | "l"] => Ok(self.list(self.computer.pc)),
+            ["list" | "l", at] => Ok(self.list(self.rom_address(at)?)),
+            ["help" | "h"] => Ok(HELP.to_string()),
+            [] => Ok(String::new()),
+            _ => Err(format!("Unknown command: {} (try help)", line.trim())),
+        }
+    }
+
+    fn rom_address(&self, text: &str) -> Result<u16, String> {
+        self.symbols.rom_address(text).ok_or_else(|| format!("Unknown ROM address or label: {}", text))
+    }
+
+    fn ram_address(&self, text: &str) -> Result<u16, String> {
+        self.symbols.ram_address(text).ok_or_else(|| format!("Unknown RAM address or variable: {}", text))
+    }
+
+    // Executes up to `count` instructions, stopping early at the halt loop, a breakpoint
+    // or a changed watched cell, then shows where it stopped.
+    fn run(&mut self, count: u64) -> String {
+        self.run_until(count, |_| false)
+    }
+
+    fn run_until(&mut self, count: u64, mut done: impl FnMut(&Computer) -> bool) -> String {
+        for _ in 0..count {
+            if self.computer.halted() {
+                return format!("Halted after {} cycles\n{}", self.computer.cycles, self.position());
+            }
+            self.computer.step();
+            let changed = self.watchpoints.iter_mut().find_map(|(address, value)| {
+                let old = std::mem::replace(value, self.computer.ram[*address as usize]);
+                (old != *value).then_some((*address, old, *value))
+            });
+            if let Some((address, old, new)) = changed {
+                let description = self.symbols.describe_ram(address);
+                return format!("{} changed: {} -> {}\n{}", description, old as i16, new as i16, self.position());
+            }
+            if self.breakpoints.contains(&self.computer.pc) {
+                return format!("Breakpoint at {}\n{}", self.symbols.describe_rom(self.computer.pc), self.position());
+            }
+            if done(&self.computer) {
+                break;
+            }
+        }
+        self.position()
+    }
+
+    // Within a call sequence, as the VM translator emits them, runs to the return label
+    // after its jump. Returning there with the stack no deeper than before the call
+    // pushed its return address tells the call apart from recursive calls made from the
+    // same place.
+    fn next(&mut self) -> String {
+        let Some(return_address) = self.call_return_address() else {
+            return self.run(1);
+        };
+        let stack_pointer = self.computer.ram[0];
+        self.run_until(u64::MAX, |computer| {
+            computer.pc == return_address && computer.ram[0] <= stack_pointer.wrapping_add(1)
+        })
+    }
+
+    // The address after the first unconditional jump from PC on, if the jump goes to a
+    // VM function or a `$$` runtime routine and a label marks the address it returns to.
+    // The code up to the jump must not branch away, other than to the stack guard, nor
+    // have labels that other code branches to.
+    fn call_return_address(&self) -> Option<u16> {
+        let rom = &self.computer.rom;
+        let pc = self.computer.pc;
+        let jump = (pc..pc.saturating_add(CALL_SEQUENCE_LENGTH).min(ROM_SIZE as u16 - 1))
+            .find(|address| rom[*address as usize] & 0xE007 == 0xE007)?;
+        let target = |jump: u16| {
+            let instruction = rom[jump.checked_sub(1)? as usize];
+            self.symbols.label_names.get(&instruction).filter(|_| instruction & 0x8000 == 0)
+        };
+        let branches_away = (pc..jump).any(|address| {
+            rom[address as usize] & 0x8007 > 0x8000 && !target(address).is_some_and(|label| label.starts_with("$$STACK"))
+        });
+        if branches_away || (pc + 1..=jump).any(|address| self.symbols.label_names.contains_key(&address)) {
+            return None;
+        }
+        let callee = target(jump)?;
+        if !(is_function_label(callee) || callee.starts_with("$$")) || !self.symbols.label_names.contains_key(&(jump + 1)) {
+            return None;
+        }
+        Some(jump + 1)
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints".to_string();
+        }
+        self.breakpoints.iter().map(|address| format!("Breakpoint at {}", self.symbols.describe_rom(*address))).collect::<Vec<_>>().join("\n")
+    }
+
+    fn list_watchpoints(&self) -> String {
+        if self.watchpoints.is_empty() {
+            return "No watchpoints".to_string();
+        }
+        self.watchpoints.keys().map(|address| format!("Watching {}", self.symbols.describe_ram(*address))).collect::<Vec<_>>().join("\n")
+    }
+
+    // `A`, `D`, `PC`, a RAM cell or an inclusive range of cells.
+    fn print(&self, what: &str) -> Result<String, String> {
+        let register = |name: &str, value: u16| format!("{} = {}", name, value as i16);
+        match what {
+            "A" => return Ok(register("A", self.computer.a)),
+            "D" => return Ok(register("D", self.computer.d)),
+            "PC" => return Ok(format!("PC = {}", self.symbols.describe_rom(self.computer.pc))),
+            _ => {},
+        }
+        let (first, last) = match what.split_once('-') {
+            Some((first, last)) => (self.ram_address(first)?, self.ram_address(last)?),
+            None => (self.ram_address(what)?, self.ram_address(what)?),
+        };
+        if first > last {
+            return Err(format!("Empty range: {}", what));
+        }
+        let cells = (first..=last)
+            .map(|address| format!("{} = {}", self.symbols.describe_ram(address), self.computer.ram[address as usize] as i16))
+            .collect::<Vec<_>>();
+        Ok(cells.join("\n"))
+    }
+
+    fn set(&mut self, what: &str, value: &str) -> Result<String, String> {
+        let value = match value.parse::<i32>() {
+            Ok(value) if (-32768..=65535).contains(&value) => value as u16,
+            _ => return Err(format!("Invalid value: {}", value)),
+        };
+        match what {
+            "A" => self.computer.a = value,
+            "D" => self.computer.d = value,
+            "PC" => self.computer.pc = value & 0x7FFF,
+            _ => {
+                let address = self.ram_address(what)?;
+                self.computer.ram[address as usize] = value;
+                if let Some(watched) = self.watchpoints.get_mut(&address) {
+                    *watched = value;
+                }
+            },
+        }
+        self.print(what)
+    }
+
+    fn registers(&self) -> String {
+        let ram = |address: usize| self.computer.ram[address] as i16;
+        format!(
+            "PC = {}  A = {}  D = {}  cycles = {}\n\
+            SP = {}  LCL = {}  ARG = {}  THIS = {}  THAT = {}",
+            self.symbols.describe_rom(self.computer.pc),
+            self.computer.a as i16,
+            self.computer.d as i16,
+            self.computer.cycles,
+            ram(0), ram(1), ram(2), ram(3), ram(4)
+        )
+    }
+
+    // The current instruction and the labels on it.
+    fn position(&self) -> String {
+        let pc = self.computer.pc;
+        self.list_range(pc, pc)
+    }
+
+    fn list(&self, at: u16) -> String {
+        self.list_range(at.saturating_sub(LIST_CONTEXT), (at + LIST_CONTEXT).min(ROM_SIZE as u16 - 1))
+    }
+
+    // Disassembly with labels on lines of their own, `=>` at PC and `*` at breakpoints.
+    // A-instructions get the label they jump to or the name of the cell they address.
+    fn list_range(&self, first: u16, last: u16) -> String {
+        let mut lines = Vec::new();
+        for address in first..=last {
+            let mut labels: Vec<&String> = self.symbols.labels.iter()
+                .filter(|(_, label_address)| **label_address == address)
+                .map(|(label, _)| label)
+                .collect();
+            labels.sort();
+            lines.extend(labels.into_iter().map(|label| format!("({})", label)));
+
+            let instruction = self.computer.rom[address as usize];
+            let marker = match (address == self.computer.pc, self.breakpoints.contains(&address)) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            let text = disassemble(instruction).unwrap_or_else(|| format!("{:016b}", instruction));
+            let next = self.computer.rom[(address as usize + 1) % ROM_SIZE];
+            let name = match instruction & 0x8000 == 0 && next & 0x8000 != 0 {
+                true if next & 0x0007 != 0 => self.symbols.label_names.get(&instruction),
+                true if next & 0x1008 != 0 => self.symbols.variable_names.get(&instruction),
+                _ => None,
+            };
+            lines.push(match name {
+                Some(name) => format!("{} {:>5}  {:<12}// {}", marker, address, text, name),
+                None => format!("{} {:>5}  {}", marker, address, text),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+// A VM function's label, `File.name`, rather than a generated `File.name$...` one.
+fn is_function_label(label: &str) -> bool {
+    label.contains('.') && !label.contains('$')
+}
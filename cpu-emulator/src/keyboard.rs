@@ -0,0 +1,113 @@
+use crate::terminal::{
+    KEY_BACKSPACE, KEY_DELETE, KEY_DOWN, KEY_END, KEY_ESCAPE, KEY_F1, KEY_HOME, KEY_INSERT, KEY_LEFT, KEY_NEWLINE,
+    KEY_PAGE_DOWN, KEY_PAGE_UP, KEY_RIGHT, KEY_UP,
+};
+use crate::{Computer, KBD};
+
+/// Key presses and releases at fixed cycle counts, so that a program reading `KBD` sees
+/// the same keys at the same points on every run.
+///
+/// A script has one event per line, in cycle order; blank lines and `//` comments are
+/// ignored:
+///
+/// ```text
+/// at cycle 10000 press 'A'
+/// at cycle 20000 release
+/// at cycle 30000 press left   // named keys: newline, left, up, f1, ...
+/// at cycle 40000 press 32     // or Hack key codes
+/// ```
+///
+/// A key stays down until the next event; pressing another key replaces it.
+#[derive(Debug, Clone, Default)]
+pub struct KeyScript {
+    // (cycle, key code), 0 for a release.
+    events: Vec<(u64, u16)>,
+    next: usize,
+}
+
+impl KeyScript {
+    /// Parses a script, reporting the first malformed line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events: Vec<(u64, u16)> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split_once("//").map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let event = parse_event(line).ok_or_else(|| format!("line {}: invalid key event: {}", index + 1, line))?;
+            if events.last().is_some_and(|(cycle, _)| *cycle > event.0) {
+                return Err(format!("line {}: cycle {} is before the previous event", index + 1, event.0));
+            }
+            events.push(event);
+        }
+        Ok(KeyScript { events, next: 0 })
+    }
+
+    /// Runs like [`Computer::run`], setting `KBD` as each event's cycle is reached. An
+    /// event at cycle n takes effect before the instruction that starts cycle n + 1.
+    pub fn run(&mut self, computer: &mut Computer, max_cycles: Option<u64>) -> bool {
+        let end = max_cycles.map(|max_cycles| computer.cycles + max_cycles);
+        loop {
+            while let Some((_, key)) = self.events.get(self.next).filter(|(cycle, _)| *cycle <= computer.cycles) {
+                computer.ram[KBD as usize] = *key;
+                self.next += 1;
+            }
+            let next_event = self.events.get(self.next).map(|(cycle, _)| *cycle);
+            let Some(stop) = [end, next_event].into_iter().flatten().min() else {
+                return computer.run(None);
+            };
+            if computer.run(Some(stop - computer.cycles)) {
+                return true;
+            }
+            if end == Some(computer.cycles) {
+                return false;
+            }
+        }
+    }
+}
+
+// `at cycle <n> press <key>` or `at cycle <n> release`.
+fn parse_event(line: &str) -> Option<(u64, u16)> {
+    let rest = line.strip_prefix("at cycle ")?.trim_start();
+    let (cycle, action) = rest.split_once(char::is_whitespace)?;
+    let cycle = cycle.parse().ok()?;
+    let action = action.trim();
+    if action == "release" {
+        return Some((cycle, 0));
+    }
+    let key = parse_key(action.strip_prefix("press")?.trim())?;
+    Some((cycle, key))
+}
+
+// A quoted printable character (`'A'`, `' '`), a key name or a key code.
+fn parse_key(text: &str) -> Option<u16> {
+    if let Some(quoted) = text.strip_prefix('\'').and_then(|text| text.strip_suffix('\'')) {
+        let mut chars = quoted.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(char @ ' '..='~'), None) => Some(char as u16),
+            _ => None,
+        };
+    }
+    let name = text.to_ascii_lowercase();
+    let code = match name.as_str() {
+        "space" => b' ' as u16,
+        "newline" | "enter" => KEY_NEWLINE,
+        "backspace" => KEY_BACKSPACE,
+        "left" => KEY_LEFT,
+        "up" => KEY_UP,
+        "right" => KEY_RIGHT,
+        "down" => KEY_DOWN,
+        "home" => KEY_HOME,
+        "end" => KEY_END,
+        "pageup" => KEY_PAGE_UP,
+        "pagedown" => KEY_PAGE_DOWN,
+        "insert" => KEY_INSERT,
+        "delete" => KEY_DELETE,
+        "escape" | "esc" => KEY_ESCAPE,
+        _ => match name.strip_prefix('f').and_then(|number| number.parse::<u16>().ok()) {
+            Some(number @ 1..=12) => KEY_F1 + number - 1,
+            _ => text.parse().ok().filter(|code| *code > 0)?,
+        },
+    };
+    Some(code)
+}
@@ -0,0 +1,211 @@
+//! Hack computer emulator (project 5): the CPU, 32K instruction ROM and data memory
+//! with the screen and keyboard maps, running `.hack` programs.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+mod debugger;
+mod keyboard;
+mod screen;
+mod terminal;
+mod test_script;
+
+pub use debugger::{Debugger, Symbols};
+pub use keyboard::KeyScript;
+pub use screen::{encode_pbm, encode_png, pbm_differences, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use terminal::{
+    decode_key, render_screen, Glyphs, KEY_BACKSPACE, KEY_DELETE, KEY_DOWN, KEY_END, KEY_ESCAPE, KEY_F1, KEY_HOME,
+    KEY_INSERT, KEY_LEFT, KEY_NEWLINE, KEY_PAGE_DOWN, KEY_PAGE_UP, KEY_RIGHT, KEY_UP,
+};
+pub use test_script::run_test_script;
+
+/// Words of instruction memory.
+pub const ROM_SIZE: usize = 32768;
+/// Words of data memory addressable by the 15 bit A register; `KBD` is the last used one.
+pub const RAM_SIZE: usize = 32768;
+/// First word of the 512x256 screen map, 32 words per row.
+pub const SCREEN: u16 = 16384;
+/// The keyboard register: the code of the key held down, or 0.
+pub const KBD: u16 = 24576;
+
+// `0;JMP`.
+const JUMP: u16 = 0b1110_1010_1000_0111;
+
+/// The computer's state. RAM and registers hold raw 16 bit words; read them as `i16`
+/// for the signed values Hack arithmetic works with.
+pub struct Computer {
+    pub rom: Vec<u16>,
+    pub ram: Vec<u16>,
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    pub cycles: u64,
+}
+
+impl Computer {
+    /// A computer with `program` at the start of ROM and everything else zeroed.
+    pub fn new(program: &[u16]) -> Self {
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        Computer { rom, ram: vec![0; RAM_SIZE], a: 0, d: 0, pc: 0, cycles: 0 }
+    }
+
+    /// Executes the instruction at PC. The M operand and jump target use A as it was
+    /// before the instruction, as on the hardware where registers change at the clock.
+    pub fn step(&mut self) {
+        let instruction = self.rom[self.pc as usize];
+        self.cycles += 1;
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = (self.pc + 1) & 0x7FFF;
+            return;
+        }
+        let address = (self.a & 0x7FFF) as usize;
+        let y = if instruction & 0x1000 != 0 { self.ram[address] } else { self.a };
+        let out = alu(self.d, y, (instruction >> 6) & 0x3F);
+        let jump = instruction & 0x7;
+        let taken = match out as i16 {
+            0 => jump & 0b010 != 0,
+            value if value < 0 => jump & 0b100 != 0,
+            _ => jump & 0b001 != 0,
+        };
+        let old_a = self.a;
+        if instruction & 0x0008 != 0 && address != KBD as usize {
+            self.ram[address] = out;
+        }
+        if instruction & 0x0020 != 0 {
+            self.a = out;
+        }
+        if instruction & 0x0010 != 0 {
+            self.d = out;
+        }
+        self.pc = if taken { old_a & 0x7FFF } else { (self.pc + 1) & 0x7FFF };
+    }
+
+    /// Whether PC sits on the usual halt loop, `(END) @END 0;JMP`, which never changes
+    /// any state again. Other loops, even `@END D;JMP`, are still running.
+    pub fn halted(&self) -> bool {
+        let next = self.rom[(self.pc as usize + 1) % ROM_SIZE];
+        self.rom[self.pc as usize] == self.pc && next == JUMP
+    }
+
+    /// Steps until the program halts or, with a limit, `max_cycles` more cycles have
+    /// run. Returns whether it halted.
+    pub fn run(&mut self, max_cycles: Option<u64>) -> bool {
+        let end = max_cycles.map(|max_cycles| self.cycles + max_cycles);
+        while !self.halted() {
+            if end.is_some_and(|end| self.cycles >= end) {
+                return false;
+            }
+            self.step();
+        }
+        true
+    }
+}
+
+// The Hack ALU: `control` holds the zx nx zy ny f no bits, zx the highest.
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |index: u16| control & (1 << (5 - index)) != 0;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) { !out } else { out }
+}
+
+// The assembler's tables, inverted: bit pattern to mnemonic. Where the computation
+// table accepts both operand orders, the one starting with D is kept.
+struct Mnemonics {
+    comp: HashMap<u16, &'static str>,
+    dest: HashMap<u16, &'static str>,
+    jump: HashMap<u16, &'static str>,
+}
+
+impl Mnemonics {
+    fn new() -> Self {
+        let invert = |table: HashMap<&'static str, &'static str>| {
+            let mut inverted: HashMap<u16, &'static str> = HashMap::new();
+            for (mnemonic, bits) in table {
+                let bits = u16::from_str_radix(bits, 2).unwrap();
+                match inverted.get(&bits) {
+                    Some(existing) if existing.starts_with('D') => {},
+                    _ => {
+                        inverted.insert(bits, mnemonic);
+                    },
+                }
+            }
+            inverted
+        };
+        Mnemonics {
+            comp: invert(assembler::comp_table()),
+            dest: invert(assembler::dest_table()),
+            jump: invert(assembler::jump_table()),
+        }
+    }
+
+    fn disassemble(&self, instruction: u16) -> Option<String> {
+        if instruction & 0x8000 == 0 {
+            return Some(format!("@{}", instruction));
+        }
+        let comp = self.comp.get(&((instruction >> 6) & 0x7F))?;
+        let dest = self.dest[&((instruction >> 3) & 0x7)];
+        let jump = self.jump[&(instruction & 0x7)];
+        let mut text = String::new();
+        if dest != "null" {
+            text.push_str(dest);
+            text.push('=');
+        }
+        text.push_str(comp);
+        if jump != "null" {
+            text.push(';');
+            text.push_str(jump);
+        }
+        Some(text)
+    }
+}
+
+/// The assembly text of an instruction, or `None` for a C-instruction whose computation
+/// bits are not in the assembler's table.
+pub fn disassemble(instruction: u16) -> Option<String> {
+    Mnemonics::new().disassemble(instruction)
+}
+
+/// Reads `.hack` text, one 16 character binary word per line. C-instructions must use a
+/// computation the assembler knows.
+pub fn load_hack(text: &str) -> Result<Vec<u16>, String> {
+    let mnemonics = Mnemonics::new();
+    let mut program = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let instruction = match u16::from_str_radix(line, 2) {
+            Ok(instruction) if line.len() == 16 => instruction,
+            _ => return Err(format!("line {}: not a 16 bit binary word: {}", index + 1, line)),
+        };
+        if mnemonics.disassemble(instruction).is_none() {
+            return Err(format!("line {}: unknown computation in {}", index + 1, line));
+        }
+        program.push(instruction);
+    }
+    if program.len() > ROM_SIZE {
+        return Err(format!("{} instructions do not fit in the {} word ROM", program.len(), ROM_SIZE));
+    }
+    Ok(program)
+}
+
+/// Reads a program from a file: `.asm` files are assembled first, anything else is read
+/// as `.hack` text.
+pub fn read_program(filepath: &Path) -> Result<Vec<u16>, String> {
+    let text = fs::read_to_string(filepath)
+        .map_err(|e| format!("Failed to read the file '{}': {}", filepath.display(), e))?;
+    let binary = if filepath.extension().is_some_and(|extension| extension == "asm") {
+        assembler::assemble(&text).map_err(|message| format!("{}: {}", filepath.display(), message))?
+    } else {
+        text
+    };
+    load_hack(&binary).map_err(|message| format!("{}: {}", filepath.display(), message))
+}
@@ -0,0 +1,367 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use cpu_emulator::{
+    decode_key, encode_pbm, encode_png, pbm_differences, read_program, render_screen, run_test_script, Computer, Debugger,
+    Glyphs, KeyScript, Symbols, KBD, SCREEN,
+};
+
+// Frames drawn per second in the terminal.
+const FRAME_RATE: u32 = 30;
+// Terminals only report key presses, so a key counts as held down until this long after
+// its last press or auto-repeat.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut program_filepath = None;
+    let mut max_cycles = None; // `--cycles <n>`: stop after n cycles even without a halt loop
+    let mut initial_ram = Vec::new(); // `--set <address>=<value>`, applied before the run
+    let mut dump = Vec::new(); // `--dump <address>[-<address>],...`, printed after the run
+    let mut profile = None; // `--profile <manifest>` written by the VM translator's --profile
+    let mut screen = None; // `--screen <file.png|file.pbm>`: the screen at the end of the run
+    let mut screen_every = None; // `--screen-every <n>`: also a numbered frame every n cycles
+    let mut expected_screen = None; // `--expect-screen <file.png|file.pbm>`: fail unless the screen matches
+    let mut terminal = None; // `--terminal <braille|blocks>`: draw the screen and read keys in the terminal
+    let mut scale = 2; // `--scale <n>`: in the terminal, one dot per n x n pixels
+    let mut speed = 4_000_000; // `--speed <n>`: in the terminal, cycles per second
+    let mut keys = None; // `--keys <script>`: key presses and releases at given cycles
+    let mut debug = false; // `--debug`: run the program under the command line debugger
+    let mut symbols = None; // `--symbols <file.asm>`: label and variable names for a `.hack` program
+    let mut arg_iter = args[1..].iter();
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--cycles" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(cycles) => max_cycles = Some(cycles),
+                Err(_) => fail(&format!("Invalid value for {}", arg)),
+            },
+            "--set" => match parse_assignment(option_value(&mut arg_iter, arg)) {
+                Some(assignment) => initial_ram.push(assignment),
+                None => fail(&format!("Invalid value for {}, expected <address>=<value>", arg)),
+            },
+            "--dump" => match parse_addresses(option_value(&mut arg_iter, arg)) {
+                Some(addresses) => dump.extend(addresses),
+                None => fail(&format!("Invalid value for {}, expected <address>[-<address>],...", arg)),
+            },
+            "--profile" => profile = Some(option_value(&mut arg_iter, arg)),
+            "--screen" => screen = Some(PathBuf::from(option_value(&mut arg_iter, arg))),
+            "--screen-every" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(0) | Err(_) => fail(&format!("Invalid value for {}", arg)),
+                Ok(cycles) => screen_every = Some(cycles),
+            },
+            "--expect-screen" => expected_screen = Some(PathBuf::from(option_value(&mut arg_iter, arg))),
+            "--terminal" => match option_value(&mut arg_iter, arg).as_str() {
+                "braille" => terminal = Some(Glyphs::Braille),
+                "blocks" => terminal = Some(Glyphs::HalfBlocks),
+                _ => fail(&format!("Invalid value for {}, expected braille or blocks", arg)),
+            },
+            "--scale" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(0) | Err(_) => fail(&format!("Invalid value for {}", arg)),
+                Ok(value) => scale = value,
+            },
+            "--speed" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(0) | Err(_) => fail(&format!("Invalid value for {}", arg)),
+                Ok(value) => speed = value,
+            },
+            "--keys" => keys = Some(option_value(&mut arg_iter, arg)),
+            "--debug" => debug = true,
+            "--symbols" => symbols = Some(option_value(&mut arg_iter, arg)),
+            _ if arg.starts_with("--") => fail(&format!("Unknown option: {}", arg)),
+            _ if program_filepath.is_none() => program_filepath = Some(arg),
+            _ => fail("Only one program can be run"),
+        }
+    }
+    let Some(program_filepath) = program_filepath else {
+        eprintln!("Usage: {} <file.hack|file.asm|file.tst> [--cycles <n>] [--set <address>=<value>]... [--dump <address>[-<address>],...]... [--profile <manifest>] [--screen <file.png|file.pbm>] [--screen-every <n>] [--expect-screen <file.png|file.pbm>] [--terminal <braille|blocks>] [--scale <n>] [--speed <cycles per second>] [--keys <script>] [--debug [--symbols <file.asm>]]", args[0]);
+        std::process::exit(1);
+    };
+    // A `.tst` script loads its own program and says what to run and output.
+    if Path::new(program_filepath).extension().is_some_and(|extension| extension == "tst") {
+        if args.len() > 2 {
+            fail("Options cannot be combined with a test script");
+        }
+        match run_test_script(Path::new(program_filepath)) {
+            Ok(message) => println!("{}", message),
+            Err(message) => fail(&message),
+        }
+        return;
+    }
+    if symbols.is_some() && !debug {
+        fail("--symbols is only used with --debug");
+    }
+    let runs = max_cycles.is_some() || !dump.is_empty() || profile.is_some() || keys.is_some();
+    if debug && (runs || screen.is_some() || expected_screen.is_some() || terminal.is_some()) {
+        fail("--debug can only be combined with --set and --symbols");
+    }
+    if screen_every.is_some() && screen.is_none() {
+        fail("--screen-every needs --screen to name the frames");
+    }
+    if screen_every.is_some() && terminal.is_some() {
+        fail("--screen-every cannot be combined with --terminal");
+    }
+
+    let program = match read_program(Path::new(program_filepath)) {
+        Ok(program) => program,
+        Err(message) => fail(&message),
+    };
+    let mut keys = match keys {
+        Some(script_filepath) => match fs::read_to_string(script_filepath) {
+            Ok(script) => KeyScript::parse(&script).unwrap_or_else(|message| fail(&format!("{}: {}", script_filepath, message))),
+            Err(e) => fail(&format!("Failed to read the file '{}': {}", script_filepath, e)),
+        },
+        None => KeyScript::default(),
+    };
+    let mut computer = Computer::new(&program);
+    for (address, value) in initial_ram {
+        computer.ram[address as usize] = value;
+    }
+    if debug {
+        // The symbols come from the program itself when it is assembly.
+        let symbols_filepath = symbols.map(Path::new)
+            .or(Some(Path::new(program_filepath)).filter(|filepath| filepath.extension().is_some_and(|extension| extension == "asm")));
+        let symbols = match symbols_filepath {
+            Some(filepath) => match fs::read_to_string(filepath) {
+                Ok(source) => Symbols::from_assembly(&source).unwrap_or_else(|message| fail(&format!("{}: {}", filepath.display(), message))),
+                Err(e) => fail(&format!("Failed to read the file '{}': {}", filepath.display(), e)),
+            },
+            None => Symbols::predefined(),
+        };
+        run_debugger(Debugger::new(computer, symbols));
+        return;
+    }
+    let halted = match (screen_every, &screen, terminal) {
+        (_, _, Some(glyphs)) => run_in_terminal(&mut computer, &mut keys, max_cycles, glyphs, scale, speed),
+        (Some(every), Some(screen), _) => run_capturing(&mut computer, &mut keys, max_cycles, every, screen),
+        _ => keys.run(&mut computer, max_cycles),
+    };
+    if halted {
+        println!("Halted after {} cycles", computer.cycles);
+    } else {
+        println!("Stopped after {} cycles", computer.cycles);
+    }
+    for address in dump {
+        println!("RAM[{}] = {}", address, computer.ram[address as usize] as i16);
+    }
+    if let Some(screen) = &screen {
+        write_screen(&computer, screen);
+    }
+    if let Some(manifest_filepath) = profile {
+        match fs::read_to_string(manifest_filepath) {
+            Ok(manifest) => print_profile(&computer, &manifest),
+            Err(e) => fail(&format!("Failed to read the file '{}': {}", manifest_filepath, e)),
+        }
+    }
+    if let Some(expected_screen) = expected_screen {
+        if !compare_screen(&computer, &expected_screen) {
+            std::process::exit(1);
+        }
+    }
+}
+
+// Runs like `KeyScript::run`, writing the screen every `every` cycles to a file named after
+// `screen` and the cycle count: `frame.png` gives `frame-000100000.png`, ...
+fn run_capturing(computer: &mut Computer, keys: &mut KeyScript, max_cycles: Option<u64>, every: u64, screen: &Path) -> bool {
+    loop {
+        let remaining = max_cycles.map(|max_cycles| max_cycles.saturating_sub(computer.cycles));
+        if remaining == Some(0) {
+            return false;
+        }
+        let cycles = remaining.map_or(every, |remaining| remaining.min(every));
+        if keys.run(computer, Some(cycles)) {
+            return true;
+        }
+        if computer.cycles.is_multiple_of(every) {
+            let stem = screen.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = screen.extension().unwrap_or_default().to_string_lossy();
+            write_screen(computer, &screen.with_file_name(format!("{}-{:09}.{}", stem, computer.cycles, extension)));
+        }
+    }
+}
+
+// Runs at `speed` cycles per second, redrawing the screen in the terminal when it changed
+// and forwarding key presses to KBD along with the scripted ones, until the program
+// halts, `max_cycles` have run or Ctrl-C is pressed. The terminal is put in raw mode with
+// `stty` for the run.
+fn run_in_terminal(
+    computer: &mut Computer,
+    keys: &mut KeyScript,
+    max_cycles: Option<u64>,
+    glyphs: Glyphs,
+    scale: usize,
+    speed: u64,
+) -> bool {
+    let stty = |args: &[&str]| {
+        let tty = File::open("/dev/tty").ok()?;
+        let output = Command::new("stty").args(args).stdin(tty).stderr(Stdio::null()).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let Some(saved_settings) = stty(&["-g"]) else {
+        fail("--terminal needs a terminal and stty");
+    };
+    stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "0"]);
+    let mut input = File::open("/dev/tty").unwrap_or_else(|_| fail("Failed to open /dev/tty"));
+    let mut output = std::io::stdout();
+    let _ = write!(output, "\x1b[?25l\x1b[2J");
+
+    let frame = Duration::from_secs(1) / FRAME_RATE;
+    let cycles_per_frame = (speed / FRAME_RATE as u64).max(1);
+    let mut last_key = None;
+    let mut last_screen = Vec::new();
+    let halted = loop {
+        let frame_start = Instant::now();
+        let mut buffer = [0; 64];
+        let read = input.read(&mut buffer).unwrap_or(0);
+        let mut position = 0;
+        let mut interrupted = false;
+        while let Some((code, length)) = decode_key(&buffer[position..read]) {
+            interrupted |= buffer[position] == 0x03;
+            if code != 0 {
+                computer.ram[KBD as usize] = code;
+                last_key = Some(frame_start);
+            }
+            position += length;
+        }
+        if interrupted {
+            break false;
+        }
+        if last_key.is_some_and(|last_key| frame_start - last_key > KEY_HOLD) {
+            computer.ram[KBD as usize] = 0;
+            last_key = None;
+        }
+
+        let remaining = max_cycles.map(|max_cycles| max_cycles.saturating_sub(computer.cycles));
+        let halted = keys.run(computer, Some(remaining.map_or(cycles_per_frame, |remaining| remaining.min(cycles_per_frame))));
+        let screen = &computer.ram[SCREEN as usize..KBD as usize];
+        if screen != last_screen {
+            last_screen = screen.to_vec();
+            let _ = write!(output, "\x1b[H{}", render_screen(computer, glyphs, scale).replace('\n', "\r\n"));
+            let _ = output.flush();
+        }
+        if halted || remaining.is_some_and(|remaining| remaining <= cycles_per_frame) {
+            break halted;
+        }
+        std::thread::sleep(frame.saturating_sub(frame_start.elapsed()));
+    };
+
+    let _ = write!(output, "\x1b[?25h");
+    let _ = output.flush();
+    stty(&[&saved_settings]);
+    halted
+}
+
+// Reads debugger commands from stdin until `quit` or the end of input. An empty line
+// repeats the previous command, as in gdb.
+fn run_debugger(mut debugger: Debugger) {
+    println!("Type help for the commands.");
+    println!("{}", debugger.execute("list").unwrap_or_default());
+    let mut previous = String::new();
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        print!("(hack) ");
+        let _ = std::io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return;
+        };
+        let line = match line.trim() {
+            "" => previous.clone(),
+            line => line.to_string(),
+        };
+        match line.as_str() {
+            "quit" | "q" => return,
+            _ => match debugger.execute(&line) {
+                Ok(output) if output.is_empty() => {},
+                Ok(output) => println!("{}", output),
+                Err(message) => println!("{}", message),
+            },
+        }
+        previous = line;
+    }
+}
+
+// PNG for `.png` files, PBM for anything else.
+fn encode_screen(computer: &Computer, filepath: &Path) -> Vec<u8> {
+    if filepath.extension().is_some_and(|extension| extension == "png") {
+        encode_png(computer)
+    } else {
+        encode_pbm(computer)
+    }
+}
+
+fn write_screen(computer: &Computer, filepath: &Path) {
+    if fs::write(filepath, encode_screen(computer, filepath)).is_err() {
+        fail(&format!("Failed to write to file: {}", filepath.display()));
+    }
+}
+
+// Compares the screen with a golden image written by `--screen`, counting the differing
+// pixels for PBM images. Returns whether they match.
+fn compare_screen(computer: &Computer, golden_filepath: &Path) -> bool {
+    let golden = match fs::read(golden_filepath) {
+        Ok(golden) => golden,
+        Err(e) => fail(&format!("Failed to read the file '{}': {}", golden_filepath.display(), e)),
+    };
+    if encode_screen(computer, golden_filepath) == golden {
+        println!("Screen matches {}", golden_filepath.display());
+        return true;
+    }
+    match pbm_differences(computer, &golden) {
+        Some(pixels) => println!("Screen differs from {} in {} pixels", golden_filepath.display(), pixels),
+        None => println!("Screen differs from {}", golden_filepath.display()),
+    }
+    false
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+// The value following an option such as `--cycles`.
+fn option_value<'a>(arg_iter: &mut impl Iterator<Item = &'a String>, option: &str) -> &'a String {
+    match arg_iter.next() {
+        Some(value) => value,
+        None => fail(&format!("Missing value for {}", option)),
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    text.parse::<u16>().ok().filter(|address| (*address as usize) < cpu_emulator::RAM_SIZE)
+}
+
+// `256=7` or `256=-7`.
+fn parse_assignment(text: &str) -> Option<(u16, u16)> {
+    let (address, value) = text.split_once('=')?;
+    Some((parse_address(address)?, value.parse::<i16>().ok()? as u16))
+}
+
+// `0,256-259`: single addresses and inclusive ranges.
+fn parse_addresses(text: &str) -> Option<Vec<u16>> {
+    let mut addresses = Vec::new();
+    for part in text.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => addresses.extend(parse_address(first)?..=parse_address(last)?),
+            None => addresses.push(parse_address(part)?),
+        }
+    }
+    Some(addresses)
+}
+
+// The manifest has a `<counter address> <function name>` line per function; prints the
+// functions by call count, most called first, leaving out those never called.
+fn print_profile(computer: &Computer, manifest: &str) {
+    let mut counts: Vec<(u16, &str)> = manifest.lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter_map(|(address, name)| Some((computer.ram[parse_address(address)? as usize], name)))
+        .filter(|(count, _)| *count > 0)
+        .collect();
+    counts.sort_by(|(count, name), (other_count, other_name)| other_count.cmp(count).then(name.cmp(other_name)));
+    println!("{:>8}  Function", "Calls");
+    for (count, name) in counts {
+        println!("{:>8}  {}", count, name);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{Computer, SCREEN};
+
+/// Screen width in pixels; each row is 32 words, the lowest bit of a word leftmost.
+pub const SCREEN_WIDTH: usize = 512;
+/// Screen height in pixels.
+pub const SCREEN_HEIGHT: usize = 256;
+
+impl Computer {
+    /// Whether the pixel at column `x` and row `y` is black.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let word = self.ram[SCREEN as usize + y * SCREEN_WIDTH / 16 + x / 16];
+        word & (1 << (x % 16)) != 0
+    }
+}
+
+// The rows of the screen packed 8 pixels per byte, leftmost pixel in the highest bit and
+// 1 for black, the layout of both PBM and (inverted) 1 bit PNG rows.
+fn packed_rows(computer: &Computer) -> Vec<Vec<u8>> {
+    (0..SCREEN_HEIGHT)
+        .map(|y| {
+            (0..SCREEN_WIDTH / 8)
+                .map(|byte| (0..8).fold(0u8, |packed, bit| packed << 1 | computer.pixel(byte * 8 + bit, y) as u8))
+                .collect()
+        })
+        .collect()
+}
+
+/// The screen as a binary PBM (P4) image.
+pub fn encode_pbm(computer: &Computer) -> Vec<u8> {
+    let mut image = format!("P4\n{} {}\n", SCREEN_WIDTH, SCREEN_HEIGHT).into_bytes();
+    for row in packed_rows(computer) {
+        image.extend(row);
+    }
+    image
+}
+
+/// The screen as a 1 bit grayscale PNG image. The pixel data goes in uncompressed
+/// deflate blocks, so the same screen always gives the same file.
+pub fn encode_png(computer: &Computer) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&(SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(SCREEN_HEIGHT as u32).to_be_bytes());
+    header.extend_from_slice(&[1, 0, 0, 0, 0]); // bit depth 1, grayscale, no interlace
+
+    // Each row starts with filter type 0; in grayscale 1 is white.
+    let mut raw = Vec::new();
+    for row in packed_rows(computer) {
+        raw.push(0);
+        raw.extend(row.iter().map(|byte| !byte));
+    }
+
+    let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+    push_chunk(&mut image, b"IHDR", &header);
+    push_chunk(&mut image, b"IDAT", &zlib_stored(&raw));
+    push_chunk(&mut image, b"IEND", &[]);
+    image
+}
+
+/// The number of pixels on which a PBM (P4) image of the screen size differs from the
+/// screen, or `None` if `image` is not such an image.
+pub fn pbm_differences(computer: &Computer, image: &[u8]) -> Option<usize> {
+    let header = format!("P4\n{} {}\n", SCREEN_WIDTH, SCREEN_HEIGHT);
+    let pixels = image.strip_prefix(header.as_bytes())?;
+    if pixels.len() != SCREEN_WIDTH * SCREEN_HEIGHT / 8 {
+        return None;
+    }
+    let screen = packed_rows(computer).concat();
+    Some(screen.iter().zip(pixels).map(|(screen, image)| (screen ^ image).count_ones() as usize).sum())
+}
+
+fn push_chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    image.extend_from_slice(kind);
+    image.extend_from_slice(data);
+    image.extend_from_slice(&crc32(&[kind, data].concat()).to_be_bytes());
+}
+
+// A zlib stream of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        stream.push((index == blocks.len() - 1) as u8);
+        stream.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        stream.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
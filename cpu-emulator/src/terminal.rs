@@ -0,0 +1,127 @@
+use crate::screen::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::Computer;
+
+/// How [`render_screen`] draws the screen with text characters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyphs {
+    /// Braille patterns, 2x4 dots per character.
+    Braille,
+    /// Upper and lower half blocks, 1x2 dots per character.
+    HalfBlocks,
+}
+
+/// Hack key codes of the keys that are not printable characters.
+pub const KEY_NEWLINE: u16 = 128;
+pub const KEY_BACKSPACE: u16 = 129;
+pub const KEY_LEFT: u16 = 130;
+pub const KEY_UP: u16 = 131;
+pub const KEY_RIGHT: u16 = 132;
+pub const KEY_DOWN: u16 = 133;
+pub const KEY_HOME: u16 = 134;
+pub const KEY_END: u16 = 135;
+pub const KEY_PAGE_UP: u16 = 136;
+pub const KEY_PAGE_DOWN: u16 = 137;
+pub const KEY_INSERT: u16 = 138;
+pub const KEY_DELETE: u16 = 139;
+pub const KEY_ESCAPE: u16 = 140;
+/// F1; F2 to F12 follow.
+pub const KEY_F1: u16 = 141;
+
+/// The screen as lines of text, one dot per `scale`x`scale` block of pixels, which is
+/// shown as set if any of its pixels is black.
+pub fn render_screen(computer: &Computer, glyphs: Glyphs, scale: usize) -> String {
+    let (dots_x, dots_y) = match glyphs {
+        Glyphs::Braille => (2, 4),
+        Glyphs::HalfBlocks => (1, 2),
+    };
+    let dot = |x: usize, y: usize| {
+        (y * scale..((y + 1) * scale).min(SCREEN_HEIGHT))
+            .any(|py| (x * scale..((x + 1) * scale).min(SCREEN_WIDTH)).any(|px| computer.pixel(px, py)))
+    };
+    let columns = SCREEN_WIDTH.div_ceil(scale * dots_x);
+    let rows = SCREEN_HEIGHT.div_ceil(scale * dots_y);
+    let mut text = String::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * dots_x, row * dots_y);
+            let glyph = match glyphs {
+                // Dots 1-3 and 7 go down the left column, 4-6 and 8 down the right one.
+                Glyphs::Braille => {
+                    let bits = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (0, 3), (1, 3)];
+                    let pattern = bits.iter()
+                        .enumerate()
+                        .filter(|(_, (dx, dy))| dot(x + dx, y + dy))
+                        .fold(0, |pattern, (bit, _)| pattern | 1 << bit);
+                    char::from_u32(0x2800 + pattern).unwrap()
+                },
+                Glyphs::HalfBlocks => match (dot(x, y), dot(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                },
+            };
+            text.push(glyph);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Decodes the first key in terminal input: its Hack key code, 0 for keys Hack does not
+/// have, and the number of bytes it took. `None` for empty input.
+pub fn decode_key(input: &[u8]) -> Option<(u16, usize)> {
+    let first = *input.first()?;
+    let key = match first {
+        b'\r' | b'\n' => (KEY_NEWLINE, 1),
+        0x08 | 0x7F => (KEY_BACKSPACE, 1),
+        0x1B => return Some(decode_escape(input)),
+        b' '..=b'~' => (first as u16, 1),
+        _ => (0, 1),
+    };
+    Some(key)
+}
+
+// ESC on its own, or the start of an escape sequence sent for an arrow, editing or
+// function key: `ESC [ A`, `ESC [ 5 ~`, `ESC O P`, ...
+fn decode_escape(input: &[u8]) -> (u16, usize) {
+    match input.get(1) {
+        Some(b'[') => {},
+        Some(b'O') => {
+            return match input.get(2) {
+                Some(key @ b'P'..=b'S') => (KEY_F1 + (key - b'P') as u16, 3),
+                Some(b'H') => (KEY_HOME, 3),
+                Some(b'F') => (KEY_END, 3),
+                Some(_) => (0, 3),
+                None => (KEY_ESCAPE, 1),
+            };
+        },
+        _ => return (KEY_ESCAPE, 1),
+    }
+    // A CSI sequence: parameters, then a final byte in @..~.
+    let Some(length) = input[2..].iter().position(|byte| (0x40..=0x7E).contains(byte)).map(|end| end + 3) else {
+        return (KEY_ESCAPE, 1);
+    };
+    let code = match &input[2..length] {
+        b"A" => KEY_UP,
+        b"B" => KEY_DOWN,
+        b"C" => KEY_RIGHT,
+        b"D" => KEY_LEFT,
+        b"H" | b"1~" | b"7~" => KEY_HOME,
+        b"F" | b"4~" | b"8~" => KEY_END,
+        b"2~" => KEY_INSERT,
+        b"3~" => KEY_DELETE,
+        b"5~" => KEY_PAGE_UP,
+        b"6~" => KEY_PAGE_DOWN,
+        b"15~" => KEY_F1 + 4,
+        b"17~" => KEY_F1 + 5,
+        b"18~" => KEY_F1 + 6,
+        b"19~" => KEY_F1 + 7,
+        b"20~" => KEY_F1 + 8,
+        b"21~" => KEY_F1 + 9,
+        b"23~" => KEY_F1 + 10,
+        b"24~" => KEY_F1 + 11,
+        _ => 0,
+    };
+    (code, length)
+}
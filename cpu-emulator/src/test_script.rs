@@ -0,0 +1,352 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{read_program, Computer, RAM_SIZE};
+
+// A command of a `.tst` script with the line it starts on.
+struct Command {
+    line: usize,
+    kind: CommandKind,
+}
+
+enum CommandKind {
+    Load(String),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Repeat(u64, Vec<Command>),
+    TickTock,
+    Output,
+    Echo(String),
+}
+
+#[derive(Clone, Copy)]
+enum Variable {
+    A,
+    D,
+    Pc,
+    Ram(u16),
+    Time,
+}
+
+// An `output-list` entry such as `RAM[0]%D2.6.2`: the value in `width` characters, with
+// `left` and `right` spaces of padding.
+#[derive(Clone)]
+struct Column {
+    name: String,
+    variable: Variable,
+    format: char,
+    left: usize,
+    width: usize,
+    right: usize,
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// Runs a nand2tetris CPU emulator test script: `load`, `output-file`, `compare-to`,
+/// `output-list`, `set`, `repeat N { ... }`, `ticktock`, `output` and `echo`. Files are
+/// named relative to the script. The output file is written in the course's column
+/// format and each line is checked against the compare file as it is output, `*` in
+/// the compare file matching any character. Stops at the first line that differs.
+pub fn run_test_script(filepath: &Path) -> Result<String, String> {
+    let source = fs::read_to_string(filepath)
+        .map_err(|e| format!("Failed to read the file '{}': {}", filepath.display(), e))?;
+    let commands = parse_script(&source).map_err(|message| format!("{}: {}", filepath.display(), message))?;
+    let mut runner = Runner {
+        directory: filepath.parent().unwrap_or(Path::new("")).to_path_buf(),
+        computer: Computer::new(&[]),
+        columns: Vec::new(),
+        output: None,
+        compare: None,
+        lines: 0,
+    };
+    let result = runner.run(&commands).map_err(|message| format!("{}: {}", filepath.display(), message));
+    if let Some((output_filepath, output)) = &runner.output {
+        fs::write(output_filepath, output)
+            .map_err(|_| format!("Failed to write to file: {}", output_filepath.display()))?;
+    }
+    result?;
+    Ok(match runner.compare {
+        Some(_) => "End of script - Comparison ended successfully".to_string(),
+        None => "End of script".to_string(),
+    })
+}
+
+struct Runner {
+    directory: PathBuf,
+    computer: Computer,
+    columns: Vec<Column>,
+    output: Option<(PathBuf, String)>,
+    compare: Option<Vec<String>>,
+    lines: usize,
+}
+
+impl Runner {
+    fn run(&mut self, commands: &[Command]) -> Result<(), String> {
+        for command in commands {
+            self.execute(command).map_err(|message| format!("line {}: {}", command.line, message))?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<(), String> {
+        match &command.kind {
+            CommandKind::Load(file) => self.computer = Computer::new(&read_program(&self.directory.join(file))?),
+            CommandKind::OutputFile(file) => self.output = Some((self.directory.join(file), String::new())),
+            CommandKind::CompareTo(file) => {
+                let filepath = self.directory.join(file);
+                let text = fs::read_to_string(&filepath)
+                    .map_err(|e| format!("Failed to read the file '{}': {}", filepath.display(), e))?;
+                self.compare = Some(text.lines().map(|line| line.trim_end().to_string()).collect());
+            },
+            CommandKind::OutputList(columns) => {
+                self.columns = columns.clone();
+                let header = self.columns.iter().map(format_header).collect::<String>();
+                self.write_line(format!("|{}", header))?;
+            },
+            CommandKind::Set(variable, value) => match variable {
+                Variable::A => self.computer.a = *value,
+                Variable::D => self.computer.d = *value,
+                Variable::Pc => self.computer.pc = *value & 0x7FFF,
+                Variable::Ram(address) => self.computer.ram[*address as usize] = *value,
+                Variable::Time => return Err("time cannot be set".to_string()),
+            },
+            CommandKind::Repeat(count, commands) => {
+                for _ in 0..*count {
+                    self.run(commands)?;
+                }
+            },
+            CommandKind::TickTock => self.computer.step(),
+            CommandKind::Output => {
+                let values = self.columns.iter().map(|column| format_value(column, self.read(column.variable))).collect::<String>();
+                self.write_line(format!("|{}", values))?;
+            },
+            CommandKind::Echo(text) => println!("{}", text),
+        }
+        Ok(())
+    }
+
+    fn read(&self, variable: Variable) -> i64 {
+        match variable {
+            Variable::A => self.computer.a as i16 as i64,
+            Variable::D => self.computer.d as i16 as i64,
+            Variable::Pc => self.computer.pc as i64,
+            Variable::Ram(address) => self.computer.ram[address as usize] as i16 as i64,
+            Variable::Time => self.computer.cycles as i64,
+        }
+    }
+
+    fn write_line(&mut self, line: String) -> Result<(), String> {
+        let Some((_, output)) = &mut self.output else {
+            return Err("No output file specified".to_string());
+        };
+        output.push_str(&line);
+        output.push('\n');
+        self.lines += 1;
+        if let Some(compare) = &self.compare {
+            let matches = compare.get(self.lines - 1).is_some_and(|expected| {
+                expected.len() == line.len() && expected.chars().zip(line.chars()).all(|(e, c)| e == '*' || e == c)
+            });
+            if !matches {
+                return Err(format!("Comparison failure at line {}", self.lines));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The column name centered over the padded value, cut to fit.
+fn format_header(column: &Column) -> String {
+    let total = column.left + column.width + column.right;
+    let name: String = column.name.chars().take(total).collect();
+    let left = (total - name.len()) / 2;
+    format!("{}{}{}|", " ".repeat(left), name, " ".repeat(total - left - name.len()))
+}
+
+// Numbers are right aligned and strings left aligned; values too wide for the column
+// keep their rightmost characters.
+fn format_value(column: &Column, value: i64) -> String {
+    let text = match column.format {
+        'B' => format!("{:016b}", value as u16),
+        'X' => format!("{:04X}", value as u16),
+        _ => value.to_string(),
+    };
+    let text = &text[text.len().saturating_sub(column.width)..];
+    let width = column.width;
+    let cell = if column.format == 'S' { format!("{:<width$}", text) } else { format!("{:>width$}", text) };
+    format!("{}{}{}|", " ".repeat(column.left), cell, " ".repeat(column.right))
+}
+
+// Words, quoted strings and the punctuation `{ } , ; !`, without `//` and `/* */`
+// comments.
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut index, mut line) = (0, 1);
+    while index < chars.len() {
+        let start_line = line;
+        match chars[index] {
+            '\n' => {
+                line += 1;
+                index += 1;
+            },
+            char if char.is_whitespace() => index += 1,
+            '/' if chars.get(index + 1) == Some(&'/') => {
+                while index < chars.len() && chars[index] != '\n' {
+                    index += 1;
+                }
+            },
+            '/' if chars.get(index + 1) == Some(&'*') => {
+                index += 2;
+                while index < chars.len() && !(chars[index] == '*' && chars.get(index + 1) == Some(&'/')) {
+                    line += (chars[index] == '\n') as usize;
+                    index += 1;
+                }
+                if index == chars.len() {
+                    return Err(format!("line {}: unterminated comment", start_line));
+                }
+                index += 2;
+            },
+            '"' => {
+                let end = chars[index + 1..].iter().position(|char| *char == '"' || *char == '\n')
+                    .filter(|end| chars[index + 1 + end] == '"')
+                    .ok_or_else(|| format!("line {}: unterminated string", start_line))?;
+                tokens.push(Token { text: chars[index..index + end + 2].iter().collect(), line });
+                index += end + 2;
+            },
+            char @ ('{' | '}' | ',' | ';' | '!') => {
+                tokens.push(Token { text: char.to_string(), line });
+                index += 1;
+            },
+            _ => {
+                let start = index;
+                while index < chars.len() && !chars[index].is_whitespace() && !"{},;!\"".contains(chars[index]) {
+                    index += 1;
+                }
+                tokens.push(Token { text: chars[start..index].iter().collect(), line });
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_script(source: &str) -> Result<Vec<Command>, String> {
+    let tokens = tokenize(source)?;
+    let mut position = 0;
+    parse_block(&tokens, &mut position, false)
+}
+
+// Commands up to the end of the script or, in a `repeat` block, the closing brace. Each
+// command ends with `,`, `;` or `!`, which only matter to the course's interactive tools.
+fn parse_block(tokens: &[Token], position: &mut usize, nested: bool) -> Result<Vec<Command>, String> {
+    let mut commands = Vec::new();
+    loop {
+        let Some(first) = tokens.get(*position) else {
+            return match nested {
+                true => Err("missing } at the end of the script".to_string()),
+                false => Ok(commands),
+            };
+        };
+        if first.text == "}" {
+            if !nested {
+                return Err(format!("line {}: unexpected }}", first.line));
+            }
+            *position += 1;
+            return Ok(commands);
+        }
+        let mut words = Vec::new();
+        while let Some(token) = tokens.get(*position).filter(|token| !matches!(token.text.as_str(), "," | ";" | "!" | "{" | "}")) {
+            words.push(token.text.as_str());
+            *position += 1;
+        }
+        let line = first.line;
+        let kind = match tokens.get(*position).map(|token| token.text.as_str()) {
+            Some("{") => {
+                *position += 1;
+                let count = match words[..] {
+                    ["repeat", count] => count.parse().map_err(|_| format!("line {}: invalid repeat count: {}", line, count))?,
+                    _ => return Err(format!("line {}: only repeat takes a block", line)),
+                };
+                CommandKind::Repeat(count, parse_block(tokens, position, true)?)
+            },
+            Some("}") | None => return Err(format!("line {}: missing , or ; after {}", line, words.join(" "))),
+            Some(_) => {
+                *position += 1;
+                if words.is_empty() {
+                    continue;
+                }
+                parse_command(&words).map_err(|message| format!("line {}: {}", line, message))?
+            },
+        };
+        commands.push(Command { line, kind });
+    }
+}
+
+fn parse_command(words: &[&str]) -> Result<CommandKind, String> {
+    let kind = match words {
+        ["load", file] => CommandKind::Load(file.to_string()),
+        ["output-file", file] => CommandKind::OutputFile(file.to_string()),
+        ["compare-to", file] => CommandKind::CompareTo(file.to_string()),
+        ["output-list", columns @ ..] => CommandKind::OutputList(columns.iter().map(|column| parse_column(column)).collect::<Result<_, _>>()?),
+        ["set", variable, value] => CommandKind::Set(
+            parse_variable(variable).ok_or_else(|| format!("Unknown variable: {}", variable))?,
+            parse_number(value).ok_or_else(|| format!("Invalid value: {}", value))?,
+        ),
+        ["ticktock"] => CommandKind::TickTock,
+        ["output"] => CommandKind::Output,
+        ["echo", text] => CommandKind::Echo(text.trim_matches('"').to_string()),
+        _ => return Err(format!("Unknown command: {}", words.join(" "))),
+    };
+    Ok(kind)
+}
+
+// `A`, `D`, `PC`, `RAM[<address>]` or `time`.
+fn parse_variable(text: &str) -> Option<Variable> {
+    let variable = match text {
+        "A" => Variable::A,
+        "D" => Variable::D,
+        "PC" => Variable::Pc,
+        "time" => Variable::Time,
+        _ => {
+            let address = text.strip_prefix("RAM[")?.strip_suffix(']')?.parse::<u16>().ok()?;
+            if address as usize >= RAM_SIZE {
+                return None;
+            }
+            Variable::Ram(address)
+        },
+    };
+    Some(variable)
+}
+
+// `<variable>%<B|D|S|X><left>.<width>.<right>`, or just the variable for `%D1.6.1`.
+fn parse_column(text: &str) -> Result<Column, String> {
+    let invalid = || format!("Invalid output-list entry: {}", text);
+    let (name, format) = text.split_once('%').unwrap_or((text, "D1.6.1"));
+    let variable = parse_variable(name).ok_or_else(|| format!("Unknown variable: {}", name))?;
+    let mut chars = format.chars();
+    let kind = chars.next().filter(|kind| "BDSX".contains(*kind)).ok_or_else(invalid)?;
+    let sizes: Vec<usize> = chars.as_str().split('.').map(|size| size.parse()).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let [left, width, right] = sizes[..] else {
+        return Err(invalid());
+    };
+    Ok(Column { name: name.to_string(), variable, format: kind, left, width, right })
+}
+
+// Decimal, possibly negative, or `%B`, `%D` and `%X` prefixed.
+fn parse_number(text: &str) -> Option<u16> {
+    let (radix, digits) = match text.strip_prefix('%') {
+        Some(text) => match text.split_at_checked(1)? {
+            ("B", digits) => (2, digits),
+            ("D", digits) => (10, digits),
+            ("X", digits) => (16, digits),
+            _ => return None,
+        },
+        None => (10, text),
+    };
+    let value = i32::from_str_radix(digits, radix).ok()?;
+    (-32768..=65535).contains(&value).then_some(value as u16)
+}
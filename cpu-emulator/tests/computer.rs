@@ -0,0 +1,139 @@
+use cpu_emulator::{load_hack, Computer};
+
+// A C-instruction from the assembler's bit patterns.
+fn instruction(comp: &str, dest: &str, jump: &str) -> u16 {
+    let bits = |table: std::collections::HashMap<&str, &str>, mnemonic: &str| {
+        u16::from_str_radix(table[mnemonic], 2).unwrap()
+    };
+    0xE000
+        | bits(assembler::comp_table(), comp) << 6
+        | bits(assembler::dest_table(), dest) << 3
+        | bits(assembler::jump_table(), jump)
+}
+
+// A computer about to run `instruction` at 100, with the given registers and M.
+fn computer(instruction: u16, a: u16, d: u16, m: u16) -> Computer {
+    let mut computer = Computer::new(&[]);
+    computer.rom[100] = instruction;
+    computer.pc = 100;
+    computer.a = a;
+    computer.d = d;
+    computer.ram[a as usize] = m;
+    computer
+}
+
+// What a computation mnemonic means, worked out from its text.
+fn evaluate(mnemonic: &str, a: u16, d: u16, m: u16) -> u16 {
+    let operand = |name: &str| match name {
+        "A" => a,
+        "D" => d,
+        "M" => m,
+        "0" => 0,
+        "1" => 1,
+        _ => panic!("unknown operand {}", name),
+    };
+    if let Some(operand_name) = mnemonic.strip_prefix('!') {
+        return !operand(operand_name);
+    }
+    if let Some(operand_name) = mnemonic.strip_prefix('-') {
+        return operand(operand_name).wrapping_neg();
+    }
+    for symbol in ['+', '-', '&', '|'] {
+        if let Some((x, y)) = mnemonic.split_once(symbol) {
+            let (x, y) = (operand(x), operand(y));
+            return match symbol {
+                '+' => x.wrapping_add(y),
+                '-' => x.wrapping_sub(y),
+                '&' => x & y,
+                _ => x | y,
+            };
+        }
+    }
+    operand(mnemonic)
+}
+
+#[test]
+fn every_computation_matches_its_mnemonic() {
+    let registers = [(1234, 5, 0xFFFD), (0x7FFF, 0x8000, 1), (0, 0xFFFF, 0x7FFF), (300, 0x5555, 0xAAAA)];
+    for (mnemonic, _) in assembler::comp_table() {
+        for (a, d, m) in registers {
+            let mut computer = computer(instruction(mnemonic, "D", "null"), a, d, m);
+            computer.step();
+            assert_eq!(computer.d, evaluate(mnemonic, a, d, m), "{} with A={} D={} M={}", mnemonic, a, d, m);
+            assert_eq!(computer.pc, 101);
+        }
+    }
+}
+
+#[test]
+fn destinations_write_their_registers_only() {
+    for (dest, _) in assembler::dest_table() {
+        let (a, d, m) = (1000, 7, 30);
+        let mut computer = computer(instruction("D+M", dest, "null"), a, d, m);
+        computer.step();
+        let written = |register: char| dest != "null" && dest.contains(register);
+        assert_eq!(computer.a, if written('A') { 37 } else { a }, "A with {}", dest);
+        assert_eq!(computer.d, if written('D') { 37 } else { d }, "D with {}", dest);
+        // M is the word A pointed to before the instruction, even when A changes.
+        assert_eq!(computer.ram[a as usize], if written('M') { 37 } else { m }, "M with {}", dest);
+        assert_eq!(computer.ram[37], 0, "RAM[37] with {}", dest);
+        assert_eq!(computer.cycles, 1);
+    }
+}
+
+#[test]
+fn the_keyboard_register_is_read_only() {
+    let mut computer = computer(instruction("-1", "M", "null"), 24576, 0, 65);
+    computer.step();
+    assert_eq!(computer.ram[24576], 65);
+}
+
+#[test]
+fn jumps_follow_the_sign_of_the_result() {
+    // Whether each jump is taken for a negative, zero and positive result.
+    let conditions = [
+        ("null", false, false, false),
+        ("JGT", false, false, true),
+        ("JEQ", false, true, false),
+        ("JGE", false, true, true),
+        ("JLT", true, false, false),
+        ("JNE", true, false, true),
+        ("JLE", true, true, false),
+        ("JMP", true, true, true),
+    ];
+    for (jump, negative, zero, positive) in conditions {
+        for out in [i16::MIN, -1, 0, 1, i16::MAX] {
+            let taken = match out {
+                0 => zero,
+                _ if out < 0 => negative,
+                _ => positive,
+            };
+            let mut computer = computer(instruction("D", "null", jump), 2000, out as u16, 0);
+            computer.step();
+            assert_eq!(computer.pc, if taken { 2000 } else { 101 }, "{} with {}", jump, out);
+        }
+    }
+}
+
+#[test]
+fn a_instructions_load_a() {
+    let mut computer = computer(0x1234, 0, 0, 0);
+    computer.step();
+    assert_eq!((computer.a, computer.pc), (0x1234, 101));
+}
+
+#[test]
+fn only_a_jump_to_itself_with_0_jmp_halts() {
+    let program = |source: &str| load_hack(&assembler::assemble(source).unwrap()).unwrap();
+    let mut computer = Computer::new(&program("@5\nD=A\n(END)\n@END\n0;JMP\n"));
+    assert!(!computer.halted());
+    assert!(computer.run(Some(100)));
+    assert_eq!((computer.pc, computer.d, computer.cycles), (2, 5, 2));
+
+    for source in ["(LOOP)\n@LOOP\nD;JMP\n", "(LOOP)\n@LOOP\nD;JEQ\n", "(LOOP)\n@LOOP\nM=0;JMP\n", "@1\n0;JMP\n"] {
+        let mut computer = Computer::new(&program(source));
+        assert!(!computer.halted(), "{}", source);
+        assert!(!computer.run(Some(100)), "{}", source);
+        assert_eq!(computer.cycles, 100);
+    }
+}
@@ -0,0 +1,118 @@
+use cpu_emulator::{load_hack, Computer, KeyScript, KBD, KEY_F1, KEY_LEFT, KEY_NEWLINE};
+
+// The key held down after running `cycles` cycles of a program that never halts.
+fn key_after(script: &str, cycles: u64) -> u16 {
+    let mut computer = Computer::new(&[]);
+    assert!(!KeyScript::parse(script).unwrap().run(&mut computer, Some(cycles)));
+    assert_eq!(computer.cycles, cycles);
+    computer.ram[KBD as usize]
+}
+
+#[test]
+fn keys_by_character_name_and_code() {
+    let cases = [
+        ("'A'", b'A' as u16),
+        ("' '", b' ' as u16),
+        ("'''", b'\'' as u16),
+        ("space", b' ' as u16),
+        ("left", KEY_LEFT),
+        ("Enter", KEY_NEWLINE),
+        ("newline", KEY_NEWLINE),
+        ("f1", KEY_F1),
+        ("F12", KEY_F1 + 11),
+        ("32", 32),
+        ("152", 152),
+    ];
+    for (key, code) in cases {
+        assert_eq!(key_after(&format!("at cycle 0 press {}", key), 1), code, "{}", key);
+    }
+}
+
+#[test]
+fn malformed_lines_are_reported() {
+    let cases = [
+        ("at cycle 10 press banana", "line 1: invalid key event: at cycle 10 press banana"),
+        ("at cycle press 'A'", "line 1: invalid key event: at cycle press 'A'"),
+        ("// keys\n\nat 10 press 'A'", "line 3: invalid key event: at 10 press 'A'"),
+        ("at cycle ten press 'A'", "line 1: invalid key event: at cycle ten press 'A'"),
+        ("at cycle 10 press", "line 1: invalid key event: at cycle 10 press"),
+        ("at cycle 10 press 'AB'", "line 1: invalid key event: at cycle 10 press 'AB'"),
+        ("at cycle 10 press 0", "line 1: invalid key event: at cycle 10 press 0"),
+        ("at cycle 10 press f13", "line 1: invalid key event: at cycle 10 press f13"),
+        ("at cycle 10 hold 'A'", "line 1: invalid key event: at cycle 10 hold 'A'"),
+        ("at cycle 20 release\nat cycle 10 press 'A'", "line 2: cycle 10 is before the previous event"),
+    ];
+    for (script, message) in cases {
+        assert_eq!(KeyScript::parse(script).unwrap_err(), message, "{}", script);
+    }
+}
+
+#[test]
+fn comments_and_blank_lines_are_ignored() {
+    let script = "// a key\n\n  at cycle 5 press 'x'   // lower case\n\nat cycle 8 release\n";
+    assert_eq!(key_after(script, 5), 0);
+    assert_eq!(key_after(script, 6), b'x' as u16);
+    assert_eq!(key_after(script, 8), b'x' as u16);
+    assert_eq!(key_after(script, 9), 0);
+}
+
+// An event at cycle n is seen by the instruction that starts cycle n + 1, however the
+// run is split up.
+#[test]
+fn keys_change_at_their_cycle() {
+    let script = "at cycle 10 press 'A'\nat cycle 20 press 'B'\nat cycle 30 release";
+    for cycles in [1, 9, 10] {
+        assert_eq!(key_after(script, cycles), 0, "after {} cycles", cycles);
+    }
+    for cycles in [11, 15, 20] {
+        assert_eq!(key_after(script, cycles), b'A' as u16, "after {} cycles", cycles);
+    }
+    for cycles in [21, 30] {
+        assert_eq!(key_after(script, cycles), b'B' as u16, "after {} cycles", cycles);
+    }
+    for cycles in [31, 1000] {
+        assert_eq!(key_after(script, cycles), 0, "after {} cycles", cycles);
+    }
+
+    let mut computer = Computer::new(&[]);
+    let mut keys = KeyScript::parse(script).unwrap();
+    let mut seen = Vec::new();
+    while computer.cycles < 40 {
+        keys.run(&mut computer, Some(1));
+        seen.push(computer.ram[KBD as usize]);
+    }
+    let expected: Vec<u16> = (1..=40)
+        .map(|cycles| match cycles {
+            11..=20 => b'A' as u16,
+            21..=30 => b'B' as u16,
+            _ => 0,
+        })
+        .collect();
+    assert_eq!(seen, expected);
+}
+
+// Waits for a key, stores it in R0, waits for its release and halts.
+const WAIT_FOR_KEY: &str = "\
+    (WAIT)\n@KBD\nD=M\n@WAIT\nD;JEQ\n\
+    @R0\nM=D\n\
+    (HOLD)\n@KBD\nD=M\n@HOLD\nD;JNE\n\
+    (END)\n@END\n0;JMP\n";
+
+#[test]
+fn a_program_reads_the_key_while_it_is_held() {
+    let program = load_hack(&assembler::assemble(WAIT_FOR_KEY).unwrap()).unwrap();
+    let script = "at cycle 10 press 'A'\nat cycle 30 release";
+
+    // The 14th instruction is the first read after the press, the 32nd the first after
+    // the release; two more leave the loop.
+    let mut computer = Computer::new(&program);
+    assert!(KeyScript::parse(script).unwrap().run(&mut computer, None));
+    assert_eq!((computer.ram[0], computer.ram[KBD as usize], computer.cycles), (65, 0, 34));
+
+    let mut computer = Computer::new(&program);
+    let mut keys = KeyScript::parse(script).unwrap();
+    assert!(!keys.run(&mut computer, Some(25)));
+    assert_eq!((computer.ram[0], computer.ram[KBD as usize]), (65, 65));
+    assert!(keys.run(&mut computer, Some(100)));
+    assert_eq!((computer.ram[KBD as usize], computer.cycles), (0, 34));
+}
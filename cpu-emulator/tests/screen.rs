@@ -0,0 +1,136 @@
+use std::fs;
+use std::process::Command;
+
+use cpu_emulator::{encode_pbm, encode_png, pbm_differences, Computer, SCREEN, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const PBM_HEADER: &[u8] = b"P4\n512 256\n";
+
+// Black pixels at (0, 0), (31, 0), (496, 255) and (511, 255).
+fn computer() -> Computer {
+    let mut computer = Computer::new(&[]);
+    computer.ram[SCREEN as usize] = 0x0001;
+    computer.ram[SCREEN as usize + 1] = 0x8000;
+    computer.ram[SCREEN as usize + 32 * 255 + 31] = 0x8001;
+    computer
+}
+
+#[test]
+fn the_lowest_bit_of_a_word_is_its_leftmost_pixel() {
+    let computer = computer();
+    assert!(computer.pixel(0, 0));
+    assert!(!computer.pixel(1, 0));
+    assert!(!computer.pixel(15, 0));
+    assert!(computer.pixel(31, 0));
+    assert!(computer.pixel(496, 255));
+    assert!(computer.pixel(511, 255));
+    let black = (0..SCREEN_HEIGHT).flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|(x, y)| computer.pixel(*x, *y))
+        .count();
+    assert_eq!(black, 4);
+}
+
+#[test]
+fn pbm_rows_start_with_the_leftmost_pixel_in_the_highest_bit() {
+    let image = encode_pbm(&computer());
+    let pixels = image.strip_prefix(PBM_HEADER).unwrap();
+    assert_eq!(pixels.len(), 512 / 8 * 256);
+    assert_eq!(pixels[..5], [0x80, 0, 0, 0x01, 0]);
+    let last_row = &pixels[255 * 64..];
+    assert_eq!(last_row[61..], [0, 0x80, 0x01]);
+    assert_eq!(pixels.iter().map(|byte| byte.count_ones()).sum::<u32>(), 4);
+}
+
+#[test]
+fn pbm_differences_counts_pixels() {
+    let computer = computer();
+    let mut image = encode_pbm(&computer);
+    assert_eq!(pbm_differences(&computer, &image), Some(0));
+    image[PBM_HEADER.len()] = 0x40;
+    assert_eq!(pbm_differences(&computer, &image), Some(2));
+    assert_eq!(pbm_differences(&computer, &image[1..]), None);
+    image.push(0);
+    assert_eq!(pbm_differences(&computer, &image), None);
+}
+
+// Splits a PNG into its chunks, checking the signature and the chunk lengths.
+fn chunks(image: &[u8]) -> Vec<(&[u8], &[u8], u32)> {
+    let mut rest = image.strip_prefix(b"\x89PNG\r\n\x1a\n").expect("PNG signature");
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+        chunks.push((&rest[4..8], &rest[8..8 + length], crc));
+        rest = &rest[12 + length..];
+    }
+    chunks
+}
+
+// The data of a zlib stream made of stored deflate blocks.
+fn stored_data(stream: &[u8]) -> Vec<u8> {
+    assert_eq!(stream[..2], [0x78, 0x01]);
+    let mut data = Vec::new();
+    let mut position = 2;
+    loop {
+        let last = stream[position] == 1;
+        let length = u16::from_le_bytes([stream[position + 1], stream[position + 2]]) as usize;
+        assert_eq!(u16::from_le_bytes([stream[position + 3], stream[position + 4]]), !(length as u16));
+        data.extend_from_slice(&stream[position + 5..position + 5 + length]);
+        position += 5 + length;
+        if last {
+            break;
+        }
+    }
+    assert_eq!(stream.len(), position + 4, "adler32 at the end");
+    data
+}
+
+#[test]
+fn png_is_a_one_bit_grayscale_image() {
+    let image = encode_png(&computer());
+    let chunks = chunks(&image);
+    let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _, _)| *kind).collect();
+    assert_eq!(kinds, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+
+    // 512 x 256, bit depth 1, grayscale, default compression and filters, no interlace.
+    let (_, header, header_crc) = chunks[0];
+    assert_eq!(header, [0, 0, 2, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0]);
+    assert_eq!(header_crc, 0xEDEB_F3CA);
+    assert_eq!(chunks[2].2, 0xAE42_6082);
+
+    // Rows of a filter byte and 64 bytes, 1 for white.
+    let rows = stored_data(chunks[1].1);
+    assert_eq!(rows.len(), 256 * 65);
+    assert_eq!(rows[..6], [0, 0x7F, 0xFF, 0xFF, 0xFE, 0xFF]);
+    assert_eq!(rows[255 * 65..][62..], [0xFF, 0x7F, 0xFE]);
+    assert!(rows.chunks(65).all(|row| row[0] == 0));
+}
+
+#[test]
+fn the_same_screen_gives_the_same_files() {
+    assert_eq!(encode_png(&computer()), encode_png(&computer()));
+    assert_ne!(encode_png(&computer()), encode_png(&Computer::new(&[])));
+}
+
+// With a budget that is not a multiple of `--screen-every`, frames are written at the
+// multiples only, and the final screen under the `--screen` name.
+#[test]
+fn frames_are_named_after_their_cycle() {
+    let directory = std::env::temp_dir().join(format!("cpu-emulator-frames-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("loop.asm"), "@SCREEN\nM=1\n(LOOP)\n@LOOP\nD;JMP\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cpu_emulator"))
+        .args(["loop.asm", "--cycles", "250", "--screen", "frame.pbm", "--screen-every", "100"])
+        .current_dir(&directory)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Stopped after 250 cycles"));
+    let mut files: Vec<String> = fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["frame-000000100.pbm", "frame-000000200.pbm", "frame.pbm", "loop.asm"]);
+    let frame = fs::read(directory.join("frame-000000100.pbm")).unwrap();
+    assert_eq!(frame[PBM_HEADER.len()], 0x80);
+}
@@ -0,0 +1,90 @@
+use cpu_emulator::{
+    decode_key, KEY_BACKSPACE, KEY_DELETE, KEY_DOWN, KEY_END, KEY_ESCAPE, KEY_F1, KEY_HOME, KEY_INSERT, KEY_LEFT,
+    KEY_NEWLINE, KEY_PAGE_DOWN, KEY_PAGE_UP, KEY_RIGHT, KEY_UP,
+};
+
+// The key codes of all the keys in `input`.
+fn decode_all(mut input: &[u8]) -> Vec<u16> {
+    let mut keys = Vec::new();
+    while let Some((key, length)) = decode_key(input) {
+        keys.push(key);
+        input = &input[length..];
+    }
+    keys
+}
+
+#[test]
+fn single_bytes() {
+    let cases: [(&[u8], u16); 8] = [
+        (b"a", b'a' as u16),
+        (b" ", b' ' as u16),
+        (b"~", b'~' as u16),
+        (b"\r", KEY_NEWLINE),
+        (b"\n", KEY_NEWLINE),
+        (b"\x7f", KEY_BACKSPACE),
+        (b"\x08", KEY_BACKSPACE),
+        (b"\x01", 0),
+    ];
+    for (input, key) in cases {
+        assert_eq!(decode_key(input), Some((key, 1)), "{:?}", input);
+    }
+    assert_eq!(decode_key(b""), None);
+}
+
+#[test]
+fn escape_sequences() {
+    let cases: [(&[u8], u16); 31] = [
+        (b"\x1b[A", KEY_UP),
+        (b"\x1b[B", KEY_DOWN),
+        (b"\x1b[C", KEY_RIGHT),
+        (b"\x1b[D", KEY_LEFT),
+        (b"\x1b[H", KEY_HOME),
+        (b"\x1b[F", KEY_END),
+        (b"\x1bOH", KEY_HOME),
+        (b"\x1bOF", KEY_END),
+        (b"\x1b[1~", KEY_HOME),
+        (b"\x1b[7~", KEY_HOME),
+        (b"\x1b[4~", KEY_END),
+        (b"\x1b[8~", KEY_END),
+        (b"\x1b[2~", KEY_INSERT),
+        (b"\x1b[3~", KEY_DELETE),
+        (b"\x1b[5~", KEY_PAGE_UP),
+        (b"\x1b[6~", KEY_PAGE_DOWN),
+        (b"\x1bOP", KEY_F1),
+        (b"\x1bOQ", KEY_F1 + 1),
+        (b"\x1bOR", KEY_F1 + 2),
+        (b"\x1bOS", KEY_F1 + 3),
+        (b"\x1b[15~", KEY_F1 + 4),
+        (b"\x1b[17~", KEY_F1 + 5),
+        (b"\x1b[18~", KEY_F1 + 6),
+        (b"\x1b[19~", KEY_F1 + 7),
+        (b"\x1b[20~", KEY_F1 + 8),
+        (b"\x1b[21~", KEY_F1 + 9),
+        (b"\x1b[23~", KEY_F1 + 10),
+        (b"\x1b[24~", KEY_F1 + 11),
+        // Keys Hack does not have still take their whole sequence.
+        (b"\x1b[1;5A", 0),
+        (b"\x1b[99~", 0),
+        (b"\x1bOx", 0),
+    ];
+    for (input, key) in cases {
+        assert_eq!(decode_key(input), Some((key, input.len())), "{:?}", input);
+    }
+    assert_eq!(KEY_F1 + 11, 152);
+}
+
+#[test]
+fn a_lone_or_truncated_escape_is_the_escape_key() {
+    let cases: [&[u8]; 5] = [b"\x1b", b"\x1b[", b"\x1b[1", b"\x1b[15", b"\x1bO"];
+    for input in cases {
+        assert_eq!(decode_key(input), Some((KEY_ESCAPE, 1)), "{:?}", input);
+    }
+    // The rest is then read as ordinary keys.
+    assert_eq!(decode_all(b"\x1b[1"), [KEY_ESCAPE, b'[' as u16, b'1' as u16]);
+    assert_eq!(decode_all(b"\x1bx"), [KEY_ESCAPE, b'x' as u16]);
+}
+
+#[test]
+fn keys_following_a_sequence_are_kept() {
+    assert_eq!(decode_all(b"\x1b[Ax\x1b[5~\r\x1bOP\x1b"), [KEY_UP, b'x' as u16, KEY_PAGE_UP, KEY_NEWLINE, KEY_F1, KEY_ESCAPE]);
+}
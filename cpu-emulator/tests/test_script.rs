@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cpu_emulator::run_test_script;
+
+// Pushes 7 and 8 and adds them, as the translation of project 7's SimpleAdd.vm does; D is
+// left holding the 8 popped last.
+const SIMPLE_ADD: &str = "\
+    @7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n\
+    @8\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n\
+    @SP\nAM=M-1\nD=M\nA=A-1\nM=D+M\n";
+
+const SIMPLE_ADD_TST: &str = "\
+load SimpleAdd.asm,
+output-file SimpleAdd.out,
+compare-to SimpleAdd.cmp,
+output-list RAM[0]%D2.6.2 RAM[256]%D2.6.2 RAM[256]%B1.16.1 D%X1.4.1 RAM[1]%D1.6.1;
+
+set RAM[0] 256,  // initializes the stack pointer
+set RAM[1] -3,
+output;
+
+repeat 60 {      // enough cycles to complete the execution
+  ticktock;
+}
+
+// Outputs the stack pointer and the stack base
+output;
+";
+
+const SIMPLE_ADD_CMP: &str = "\
+|  RAM[0]  | RAM[256] |     RAM[256]     |  D   | RAM[1] |
+|     256  |       0  | 0000000000000000 | 0000 |     -3 |
+|     257  |      15  | 0000000000001111 | 0008 |     -3 |
+";
+
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("test-script-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    for (file, text) in files {
+        fs::write(directory.join(file), text).unwrap();
+    }
+    directory
+}
+
+#[test]
+fn output_matches_the_compare_file() {
+    let directory = project("pass", &[("SimpleAdd.asm", SIMPLE_ADD), ("SimpleAdd.tst", SIMPLE_ADD_TST), ("SimpleAdd.cmp", SIMPLE_ADD_CMP)]);
+    let result = run_test_script(&directory.join("SimpleAdd.tst"));
+    assert_eq!(result.unwrap(), "End of script - Comparison ended successfully");
+    let output = fs::read_to_string(directory.join("SimpleAdd.out")).unwrap();
+    for (line, (actual, expected)) in output.lines().zip(SIMPLE_ADD_CMP.lines()).enumerate() {
+        assert_eq!(actual, expected, "line {}", line + 1);
+    }
+    assert_eq!(output, SIMPLE_ADD_CMP);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn stars_in_the_compare_file_match_anything() {
+    let compare = SIMPLE_ADD_CMP.replace("      15  |", "      **  |");
+    let directory = project("stars", &[("SimpleAdd.asm", SIMPLE_ADD), ("SimpleAdd.tst", SIMPLE_ADD_TST), ("SimpleAdd.cmp", &compare)]);
+    assert!(run_test_script(&directory.join("SimpleAdd.tst")).is_ok());
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn a_differing_line_is_reported() {
+    let compare = SIMPLE_ADD_CMP.replace("      15  |", "      16  |");
+    let directory = project("fail", &[("SimpleAdd.asm", SIMPLE_ADD), ("SimpleAdd.tst", SIMPLE_ADD_TST), ("SimpleAdd.cmp", &compare)]);
+    let filepath = directory.join("SimpleAdd.tst");
+    // The third output line, written by the `output` on line 15 of the script.
+    assert_eq!(
+        run_test_script(&filepath).unwrap_err(),
+        format!("{}: line 15: Comparison failure at line 3", filepath.display())
+    );
+    // The output is kept up to the line that differs.
+    let output = fs::read_to_string(directory.join("SimpleAdd.out")).unwrap();
+    assert_eq!(output, SIMPLE_ADD_CMP);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn a_missing_line_is_reported() {
+    let compare: String = SIMPLE_ADD_CMP.lines().take(2).map(|line| format!("{}\n", line)).collect();
+    let directory = project("short", &[("SimpleAdd.asm", SIMPLE_ADD), ("SimpleAdd.tst", SIMPLE_ADD_TST), ("SimpleAdd.cmp", &compare)]);
+    let error = run_test_script(&directory.join("SimpleAdd.tst")).unwrap_err();
+    assert!(error.ends_with("line 15: Comparison failure at line 3"), "{}", error);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn script_errors_name_their_line() {
+    let cases = [
+        ("output-list RAM[0]%D2.6.2;\nrepeat 2 {\n  tick;\n}\n", "line 3: Unknown command: tick"),
+        ("set RAM[0] 256,\nrepeat ten {\n  ticktock;\n}\n", "line 2: invalid repeat count: ten"),
+        ("repeat 2 {\n  ticktock;\n", "missing } at the end of the script"),
+        ("output-list RAM[0]%Q1.6.1;\n", "line 1: Invalid output-list entry: RAM[0]%Q1.6.1"),
+        ("set RAM[0] 70000,\n", "line 1: Invalid value: 70000"),
+        ("ticktock\n", "line 1: missing , or ; after ticktock"),
+    ];
+    let directory = project("errors", &[]);
+    let filepath = directory.join("Errors.tst");
+    for (script, message) in cases {
+        fs::write(&filepath, script).unwrap();
+        assert_eq!(run_test_script(&filepath).unwrap_err(), format!("{}: {}", filepath.display(), message), "{}", script);
+    }
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::parser::{CommandType, VmCommand, VmModule};
+
+// Words pushed by `call` between the arguments and the callee's locals.
+const FRAME_SIZE: usize = 5;
+
+/// Stack facts about one function, computed from its commands alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionAnalysis {
+    pub name: String,
+    pub module: String,
+    pub num_locals: usize,
+    /// Highest operand stack height reached, not counting the locals.
+    pub max_depth: usize,
+    /// Every call made, with the stack height just before it (arguments included).
+    pub calls: Vec<(String, usize)>,
+    /// Paths that disagree on the stack height at a label or `return`, or pop from an
+    /// empty stack, as `File.vm:line: message`.
+    pub issues: Vec<String>,
+}
+
+/// The stack usage of the deepest call chain starting at some function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorstCase {
+    /// Words used above the caller's stack: callee frames, locals and operand stacks.
+    pub words: usize,
+    pub chain: Vec<String>,
+    /// Calls that close a cycle; they are left out of `words` since recursion has no bound.
+    pub recursive_calls: BTreeSet<(String, String)>,
+    /// Called functions that are not defined anywhere, counted as using no stack.
+    pub undefined: BTreeSet<String>,
+}
+
+/// Analyzes every function of a program.
+pub fn analyze(modules: &[VmModule]) -> Vec<FunctionAnalysis> {
+    let mut functions = Vec::new();
+    for module in modules {
+        let starts: Vec<usize> = module.commands.iter()
+            .enumerate()
+            .filter(|(_, command)| matches!(command.command, CommandType::Function(..)))
+            .map(|(position, _)| position)
+            .collect();
+        for (index, start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(module.commands.len());
+            functions.push(analyze_function(&module.name, &module.commands[*start..end]));
+        }
+    }
+    functions
+}
+
+// `commands` starts with the `function` command and ends before the next one.
+fn analyze_function(module: &str, commands: &[VmCommand]) -> FunctionAnalysis {
+    let CommandType::Function(name, num_locals) = &commands[0].command else {
+        unreachable!("a function starts with its declaration");
+    };
+    let mut analysis = FunctionAnalysis {
+        name: name.clone(),
+        module: module.to_string(),
+        num_locals: *num_locals,
+        max_depth: 0,
+        calls: Vec::new(),
+        issues: Vec::new(),
+    };
+    let labels: HashMap<&str, usize> = commands.iter()
+        .enumerate()
+        .filter_map(|(position, command)| match &command.command {
+            CommandType::Label(label) => Some((label.as_str(), position)),
+            _ => None,
+        })
+        .collect();
+    // Stack height before each command, on the first path found to reach it.
+    let mut heights: Vec<Option<usize>> = vec![None; commands.len()];
+    let mut return_height = None;
+    let mut pending = vec![(1, 0)];
+    while let Some((position, height)) = pending.pop() {
+        let Some(vm_command) = commands.get(position) else {
+            continue;
+        };
+        let location = format!("{}.vm:{}", module, vm_command.line);
+        match heights[position] {
+            Some(known) if known != height => {
+                analysis.issues.push(format!(
+                    "{}: `{}` reached with stack height {} and {}",
+                    location, vm_command.command, known, height
+                ));
+                continue;
+            },
+            Some(_) => continue,
+            None => heights[position] = Some(height),
+        }
+        let (popped, pushed) = stack_effect(&vm_command.command);
+        if height < popped {
+            analysis.issues.push(format!("{}: `{}` pops from an empty stack", location, vm_command.command));
+            continue;
+        }
+        let next_height = height - popped + pushed;
+        analysis.max_depth = analysis.max_depth.max(next_height);
+        match &vm_command.command {
+            CommandType::Goto(label) | CommandType::If(label) => {
+                match labels.get(label.as_str()) {
+                    Some(target) => pending.push((*target, next_height)),
+                    None => analysis.issues.push(format!("{}: label {} is not defined in {}", location, label, name)),
+                }
+                if matches!(vm_command.command, CommandType::If(_)) {
+                    pending.push((position + 1, next_height));
+                }
+            },
+            CommandType::Return => match return_height {
+                Some(known) if known != height => analysis.issues.push(format!(
+                    "{}: `return` with stack height {}, but {} on another path",
+                    location, height, known
+                )),
+                _ => return_height = Some(height),
+            },
+            CommandType::Call(callee, _) => {
+                analysis.calls.push((callee.clone(), height));
+                pending.push((position + 1, next_height));
+            },
+            _ => pending.push((position + 1, next_height)),
+        }
+    }
+    analysis
+}
+
+// Values popped and pushed by a command; `return` needs the value it returns.
+pub(crate) fn stack_effect(command: &CommandType) -> (usize, usize) {
+    match command {
+        CommandType::Push(..) => (0, 1),
+        CommandType::Pop(..) | CommandType::If(_) | CommandType::Return => (1, 0),
+        CommandType::Arithmetic(operation) if operation == "neg" || operation == "not" => (1, 1),
+        CommandType::Arithmetic(_) => (2, 1),
+        CommandType::Call(_, num_args) => (*num_args, 1),
+        CommandType::Label(_) | CommandType::Goto(_) | CommandType::Function(..) => (0, 0),
+    }
+}
+
+/// The deepest chain of calls starting at `root`, following every call site and
+/// skipping calls back into a function already on the chain. Each function is only
+/// explored once, so with mutual recursion the result for a function reached through
+/// different cycles may be slightly lower than the true maximum.
+pub fn worst_case(functions: &[FunctionAnalysis], root: &str) -> WorstCase {
+    let by_name: HashMap<&str, &FunctionAnalysis> = functions.iter()
+        .map(|function| (function.name.as_str(), function))
+        .collect();
+    let mut worst_case = WorstCase {
+        words: 0,
+        chain: Vec::new(),
+        recursive_calls: BTreeSet::new(),
+        undefined: BTreeSet::new(),
+    };
+    let mut on_chain = Vec::new();
+    let mut known = HashMap::new();
+    let (words, chain) = deepest_chain(root, &by_name, &mut on_chain, &mut known, &mut worst_case);
+    worst_case.words = words;
+    worst_case.chain = chain;
+    worst_case
+}
+
+fn deepest_chain<'a>(
+    name: &'a str,
+    by_name: &HashMap<&str, &'a FunctionAnalysis>,
+    on_chain: &mut Vec<&'a str>,
+    known: &mut HashMap<&'a str, (usize, Vec<String>)>,
+    worst_case: &mut WorstCase,
+) -> (usize, Vec<String>) {
+    if let Some(result) = known.get(name) {
+        return result.clone();
+    }
+    let Some(function) = by_name.get(name) else {
+        worst_case.undefined.insert(name.to_string());
+        return (0, vec![name.to_string()]);
+    };
+    on_chain.push(name);
+    let mut words = function.max_depth;
+    let mut deepest_callees = Vec::new();
+    for (callee, height) in &function.calls {
+        if on_chain.contains(&callee.as_str()) {
+            worst_case.recursive_calls.insert((name.to_string(), callee.clone()));
+            continue;
+        }
+        let (callee_words, callee_chain) = deepest_chain(callee, by_name, on_chain, known, worst_case);
+        if height + FRAME_SIZE + callee_words > words {
+            words = height + FRAME_SIZE + callee_words;
+            deepest_callees = callee_chain;
+        }
+    }
+    on_chain.pop();
+    let mut chain = vec![name.to_string()];
+    chain.extend(deepest_callees);
+    known.insert(name, (function.num_locals + words, chain.clone()));
+    (function.num_locals + words, chain)
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::parser::{is_label, CommandType, VmModule};
+use crate::Error;
+
+// Layout of a bytecode file (`.vmb`), all integers as unsigned LEB128 unless noted:
+//
+//   "HVMB" magic, then the format version as a little-endian u16
+//   string count, then each string as its byte length and UTF-8 bytes
+//   module count, then each module as its name (a string index), its command count
+//   and its commands
+//
+// A command is an opcode byte followed by its operands. Names, labels and segments
+// or operations outside the standard set are string indexes; `push`/`pop` indexes are
+// the 16 bits of the `i16`. Source lines are not kept: read commands are numbered from
+// 1, which matches the canonical text written for them one per line.
+const MAGIC: &[u8; 4] = b"HVMB";
+
+/// The bytecode format version written by [`write_bytecode`], the only one it reads.
+pub const BYTECODE_VERSION: u16 = 1;
+
+const ARITHMETIC: [&str; 9] = ["add", "sub", "neg", "eq", "gt", "lt", "and", "or", "not"];
+const SEGMENTS: [&str; 8] = ["argument", "local", "static", "constant", "this", "that", "pointer", "temp"];
+
+// 0x00 + i: ARITHMETIC[i]; 0x10 + i / 0x20 + i: push / pop SEGMENTS[i].
+const ARITHMETIC_NAMED: u8 = 0x0F;
+const PUSH: u8 = 0x10;
+const PUSH_NAMED: u8 = 0x18;
+const POP: u8 = 0x20;
+const POP_NAMED: u8 = 0x28;
+const LABEL: u8 = 0x30;
+const GOTO: u8 = 0x31;
+const IF_GOTO: u8 = 0x32;
+const FUNCTION: u8 = 0x33;
+const RETURN: u8 = 0x34;
+const CALL: u8 = 0x35;
+
+/// Serializes modules, in order, into the bytecode format read by [`read_bytecode`].
+pub fn write_bytecode(modules: &[VmModule]) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut body = Vec::new();
+    write_number(&mut body, modules.len());
+    for module in modules {
+        write_number(&mut body, strings.index(&module.name));
+        write_number(&mut body, module.commands.len());
+        for vm_command in &module.commands {
+            write_command(&mut body, &mut strings, &vm_command.command);
+        }
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+    write_number(&mut bytes, strings.strings.len());
+    for string in &strings.strings {
+        write_number(&mut bytes, string.len());
+        bytes.extend_from_slice(string.as_bytes());
+    }
+    bytes.extend(body);
+    bytes
+}
+
+/// Reads the modules of a bytecode file; `file` names it in errors.
+pub fn read_bytecode(file: &str, bytes: &[u8]) -> Result<Vec<VmModule>, Error> {
+    let mut reader = Reader { file, bytes, position: 0, strings: Vec::new() };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(reader.error("not a VM bytecode file"));
+    }
+    let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    if version != BYTECODE_VERSION {
+        return Err(reader.error(&format!("unsupported version {}", version)));
+    }
+    let mut strings = Vec::new();
+    for _ in 0..reader.number()? {
+        let length = reader.number()?;
+        let string = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| reader.error("string is not UTF-8"))?;
+        strings.push(string.to_string());
+    }
+    reader.strings = strings;
+
+    let mut modules = Vec::new();
+    for _ in 0..reader.number()? {
+        let name = reader.string()?;
+        let mut commands = Vec::new();
+        for _ in 0..reader.number()? {
+            commands.push(reader.command()?);
+        }
+        modules.push(VmModule::from_commands(&name, commands));
+    }
+    if reader.position != bytes.len() {
+        return Err(reader.error("unexpected data after the last module"));
+    }
+    Ok(modules)
+}
+
+fn write_command(bytes: &mut Vec<u8>, strings: &mut StringTable, command: &CommandType) {
+    match command {
+        CommandType::Arithmetic(operation) => match ARITHMETIC.iter().position(|known| known == operation) {
+            Some(opcode) => bytes.push(opcode as u8),
+            None => {
+                bytes.push(ARITHMETIC_NAMED);
+                write_number(bytes, strings.index(operation));
+            },
+        },
+        CommandType::Push(segment, index) => write_access(bytes, strings, PUSH, PUSH_NAMED, segment, *index),
+        CommandType::Pop(segment, index) => write_access(bytes, strings, POP, POP_NAMED, segment, *index),
+        CommandType::Label(label) => write_named(bytes, strings, LABEL, label),
+        CommandType::Goto(label) => write_named(bytes, strings, GOTO, label),
+        CommandType::If(label) => write_named(bytes, strings, IF_GOTO, label),
+        CommandType::Function(name, num_locals) => {
+            write_named(bytes, strings, FUNCTION, name);
+            write_number(bytes, *num_locals);
+        },
+        CommandType::Return => bytes.push(RETURN),
+        CommandType::Call(name, num_args) => {
+            write_named(bytes, strings, CALL, name);
+            write_number(bytes, *num_args);
+        },
+    }
+}
+
+fn write_access(bytes: &mut Vec<u8>, strings: &mut StringTable, opcode: u8, named: u8, segment: &str, index: i16) {
+    match SEGMENTS.iter().position(|known| *known == segment) {
+        Some(segment) => bytes.push(opcode + segment as u8),
+        None => write_named(bytes, strings, named, segment),
+    }
+    write_number(bytes, index as u16 as usize);
+}
+
+fn write_named(bytes: &mut Vec<u8>, strings: &mut StringTable, opcode: u8, name: &str) {
+    bytes.push(opcode);
+    write_number(bytes, strings.index(name));
+}
+
+fn write_number(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// Strings in order of first use, each stored once.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    indexes: HashMap<String, usize>,
+}
+
+impl StringTable {
+    fn index(&mut self, string: &str) -> usize {
+        if let Some(index) = self.indexes.get(string) {
+            return *index;
+        }
+        self.strings.push(string.to_string());
+        self.indexes.insert(string.to_string(), self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+}
+
+struct Reader<'a> {
+    file: &'a str,
+    bytes: &'a [u8],
+    position: usize,
+    strings: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> Error {
+        Error::Bytecode { file: self.file.to_string(), offset: self.position, message: message.to_string() }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if length > self.bytes.len() - self.position {
+            return Err(self.error("unexpected end of file"));
+        }
+        self.position += length;
+        let bytes: &'a [u8] = self.bytes;
+        Ok(&bytes[self.position - length..self.position])
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7F) as usize;
+            if shift >= usize::BITS || bits << shift >> shift != bits {
+                return Err(self.error("number too large"));
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let index = self.number()?;
+        match self.strings.get(index) {
+            Some(string) => Ok(string.clone()),
+            None => Err(self.error(&format!("string index {} out of range", index))),
+        }
+    }
+
+    fn label(&mut self) -> Result<String, Error> {
+        let label = self.string()?;
+        if !is_label(&label) {
+            return Err(self.error(&format!("label `{}` contains $", label)));
+        }
+        Ok(label)
+    }
+
+    fn index(&mut self) -> Result<i16, Error> {
+        match u16::try_from(self.number()?) {
+            Ok(index) => Ok(index as i16),
+            Err(_) => Err(self.error("segment index out of range")),
+        }
+    }
+
+    fn command(&mut self) -> Result<CommandType, Error> {
+        let opcode = self.byte()?;
+        let command = match opcode {
+            _ if (opcode as usize) < ARITHMETIC.len() => CommandType::Arithmetic(ARITHMETIC[opcode as usize].to_string()),
+            ARITHMETIC_NAMED => CommandType::Arithmetic(self.string()?),
+            PUSH..PUSH_NAMED => CommandType::Push(SEGMENTS[(opcode - PUSH) as usize].to_string(), self.index()?),
+            PUSH_NAMED => CommandType::Push(self.string()?, self.index()?),
+            POP..POP_NAMED => CommandType::Pop(SEGMENTS[(opcode - POP) as usize].to_string(), self.index()?),
+            POP_NAMED => CommandType::Pop(self.string()?, self.index()?),
+            LABEL => CommandType::Label(self.label()?),
+            GOTO => CommandType::Goto(self.label()?),
+            IF_GOTO => CommandType::If(self.label()?),
+            FUNCTION => CommandType::Function(self.string()?, self.number()?),
+            RETURN => CommandType::Return,
+            CALL => CommandType::Call(self.string()?, self.number()?),
+            _ => {
+                self.position -= 1;
+                return Err(self.error(&format!("unknown opcode {:#04x}", opcode)));
+            },
+        };
+        Ok(command)
+    }
+}
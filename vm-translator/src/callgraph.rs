@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::parser::{CommandType, VmModule};
+
+/// Which functions each function calls, built from the `function` and `call` commands
+/// of a whole program. Every defined function has an entry, even if it calls nothing.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CallGraph {
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    pub fn new(modules: &[VmModule]) -> Self {
+        let mut calls: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for module in modules {
+            let mut caller = None;
+            for command in &module.commands {
+                match &command.command {
+                    CommandType::Function(name, _) => {
+                        calls.entry(name.clone()).or_default();
+                        caller = Some(name.clone());
+                    },
+                    CommandType::Call(name, _) => {
+                        if let Some(caller) = &caller {
+                            calls.entry(caller.clone()).or_default().insert(name.clone());
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        CallGraph { calls }
+    }
+
+    /// The functions that can run when execution starts at `root`, `root` included.
+    pub fn reachable_from(&self, root: &str) -> BTreeSet<String> {
+        let mut reachable = BTreeSet::from([root.to_string()]);
+        let mut pending = vec![root];
+        while let Some(function) = pending.pop() {
+            for callee in self.calls.get(function).into_iter().flatten() {
+                if reachable.insert(callee.clone()) {
+                    pending.push(callee);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// The graph in Graphviz DOT format, with functions unreachable from `root` dashed.
+    pub fn to_dot(&self, root: &str) -> String {
+        let reachable = self.reachable_from(root);
+        let mut dot = String::from("digraph calls {\n");
+        for function in self.calls.keys() {
+            if function == root {
+                dot.push_str(&format!("    \"{}\" [shape=box];\n", function));
+            } else if !reachable.contains(function) {
+                dot.push_str(&format!("    \"{}\" [style=dashed, color=gray, fontcolor=gray];\n", function));
+            }
+        }
+        for (caller, callees) in &self.calls {
+            for callee in callees {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", caller, callee));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Drops every function that cannot be reached from `root`. Commands before the first
+/// `function` of a module are kept.
+pub fn eliminate_dead_functions(modules: &[VmModule], root: &str) -> Vec<VmModule> {
+    let reachable = CallGraph::new(modules).reachable_from(root);
+    modules.iter()
+        .map(|module| {
+            let mut keep = true;
+            let commands = module.commands.iter()
+                .filter(|command| {
+                    if let CommandType::Function(name, _) = &command.command {
+                        keep = reachable.contains(name);
+                    }
+                    keep
+                })
+                .cloned()
+                .collect();
+            VmModule { name: module.name.clone(), commands }
+        })
+        .collect()
+}
@@ -0,0 +1,1368 @@
+use std::collections::HashMap;
+
+use crate::parser::{CommandType, VmCommand, VmModule};
+use crate::Error;
+
+/// Code generation switches; the default is the plain, standard translation.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Prefix every command's block with `// File.vm:line: command`.
+    pub comments: bool,
+    /// Route call/return/eq/gt/lt through shared routines emitted once in the preamble,
+    /// and zero more than a couple of function locals with a loop rather than unrolled.
+    pub optimize_size: bool,
+    /// Compare with a bare `x - y`, which is shorter but wrong when the subtraction overflows.
+    pub unchecked_compare: bool,
+    /// Fold constants before code generation and emit specialized code for common sequences
+    /// such as `push constant 1; add` or `push local 0; pop that 1`.
+    pub optimize: bool,
+    /// Keep the top of the stack in D between commands instead of writing it to `*SP`
+    /// and reading it back; it is spilled to memory before labels, calls, returns, jumps
+    /// and any command that needs the whole stack in memory.
+    pub cache_tos: bool,
+    /// Drop functions that cannot be reached from `Sys.init` (whole programs only).
+    pub eliminate_dead_functions: bool,
+    /// Turn `call f n` directly followed by `return` into a jump that reuses the
+    /// caller's frame, so tail recursion runs in constant stack space.
+    pub tail_calls: bool,
+    /// Inline leaf functions of at most this many commands at their call sites; 0 disables it.
+    pub inline_limit: usize,
+    /// Check SP against this limit after every call builds its frame and every function
+    /// initializes its locals, halting with an error code in `STACK_ERROR_ADDRESS`.
+    pub stack_limit: Option<u16>,
+    /// Count the calls of every function in a RAM word from `PROFILE_BASE`, incremented on
+    /// entry; `Translation::profile` lists which word is whose. Turns off inlining, which
+    /// would skip the counters.
+    pub profile: bool,
+    /// Accept the `mult`, `div`, `mod`, `shl` and `shr` commands, which are not part of the
+    /// standard VM, and implement them with shared routines emitted in the preamble.
+    pub extensions: bool,
+}
+
+/// The `stack_limit` used by `--check-stack`: the last word below the heap.
+pub const DEFAULT_STACK_LIMIT: u16 = 2047;
+/// Where a failed stack check leaves its error code: the last heap word, below the screen.
+pub const STACK_ERROR_ADDRESS: u16 = 16383;
+/// Error code for a stack overflow detected in a `call`, after pushing the frame.
+pub const STACK_ERROR_CALL: u16 = 1;
+/// Error code for a stack overflow detected in a `function`, after pushing its locals.
+pub const STACK_ERROR_FUNCTION: u16 = 2;
+
+// Functions with more locals than this zero them with a loop instead of unrolled
+// stores: by default the unrolled form (2 instructions per local) is kept for speed
+// until it grows large, with `optimize_size` as soon as the 9 instruction loop is shorter.
+const UNROLLED_LOCALS: usize = 16;
+const UNROLLED_LOCALS_SIZE: usize = 2;
+
+/// The first of the call counters kept with `profile`, one word per function up to
+/// `STACK_ERROR_ADDRESS`: the top of the heap, which the OS allocates last.
+pub const PROFILE_BASE: u16 = 16128;
+
+/// The `inline_limit` used by `--optimize` unless told otherwise.
+pub const DEFAULT_INLINE_LIMIT: usize = 8;
+
+// Where a segment entry lives: a fixed symbol or address, or `index` words past the
+// address held in a base pointer (LCL, ARG, THIS or THAT).
+enum Location {
+    Direct(String),
+    Indirect(&'static str, i16),
+}
+
+// Per-translation state. Label numbering lives here rather than in a global so the
+// output only depends on the input, and the current file and function are known for
+// static symbols and scoped labels.
+pub(crate) struct Translator<'a> {
+    options: &'a Options,
+    profile_counters: Option<&'a HashMap<String, u16>>, // with `profile`: function name to counter address
+    file_name: String,   // `Main.vm`, used in comments
+    module_name: String, // `Main`, the prefix of static symbols
+    function_name: Option<String>,
+    label_counter: usize,
+    tos_in_d: bool, // with `cache_tos`: the top of the stack is in D, not yet pushed
+}
+
+impl<'a> Translator<'a> {
+    pub(crate) fn new(module_name: &str, options: &'a Options, profile_counters: Option<&'a HashMap<String, u16>>) -> Self {
+        Translator {
+            options,
+            profile_counters,
+            file_name: format!("{}.vm", module_name),
+            module_name: module_name.to_string(),
+            function_name: None,
+            label_counter: 0,
+            tos_in_d: false,
+        }
+    }
+
+    // Generated labels are prefixed with the enclosing function, e.g. `Main.main$$EQ3`,
+    // or with the file name outside of any function. The second `$` keeps them apart
+    // from scoped VM labels, which cannot contain one.
+    fn unique_label(&mut self, base: &str) -> String {
+        let scope = self.function_name.as_deref().unwrap_or(&self.module_name);
+        let label = format!("{}$${}{}", scope, base, self.label_counter);
+        self.label_counter += 1;
+        label
+    }
+
+    // VM labels are local to the function declaring them: `label LOOP` in `Main.main`
+    // becomes `Main.main$LOOP`.
+    fn scoped_label(&self, label: &str) -> String {
+        match &self.function_name {
+            Some(function_name) => format!("{}${}", function_name, label),
+            None => label.to_string(),
+        }
+    }
+
+    // Returns the assembly together with the VM line each emitted assembly line came from.
+    pub(crate) fn convert_to_asm(&mut self, parsed_content: &[VmCommand]) -> Result<(String, Vec<usize>), Error> {
+        let options = self.options;
+        let mut asm_result = String::new();
+        let mut source_map = Vec::new();
+        let mut position = 0;
+        while position < parsed_content.len() {
+            let block_start = asm_result.len();
+            let mut fused = if options.cache_tos { self.format_cached(&parsed_content[position..]) } else { None };
+            let spill = if fused.is_none() { self.spill() } else { "" };
+            if fused.is_none() && options.tail_calls {
+                fused = self.format_tail_call(&parsed_content[position..]);
+            }
+            if fused.is_none() && options.optimize {
+                fused = self.format_fused(&parsed_content[position..]);
+            }
+            let consumed = fused.as_ref().map_or(1, |(_, consumed)| *consumed);
+            let block = &parsed_content[position..position + consumed];
+            position += consumed;
+            if options.comments {
+                for VmCommand { line, command } in block {
+                    asm_result.push_str(&format!("// {}:{}: {}\n", self.file_name, line, command));
+                }
+            }
+            asm_result.push_str(spill);
+            let VmCommand { line, command } = &block[0];
+            if let Some((asm_code, _)) = fused {
+                asm_result.push_str(&asm_code);
+            } else {
+                match command {
+                    CommandType::Push(segment, index) => match self.format_load(segment, *index) {
+                        Some(asm_code) => {
+                            asm_result.push_str(&asm_code);
+                            asm_result.push_str(self.push_d());
+                        },
+                        None => return Err(self.invalid_command(*line, command)),
+                    },
+                    CommandType::Pop(segment, index) => match self.locate(segment, *index) {
+                        Some(location) if options.optimize => asm_result.push_str(&format_move(
+                            "@SP\n\
+                            AM=M-1\n\
+                            D=M\n",
+                            &location
+                        )),
+                        Some(location) => asm_result.push_str(&format_pop(&location)),
+                        None => return Err(self.invalid_command(*line, command)),
+                    },
+                    CommandType::Arithmetic(operation) if options.optimize_size && is_comparison(operation) => {
+                        asm_result.push_str(&self.format_routine_call(operation));
+                    },
+                    CommandType::Arithmetic(operation) => match self.format_arithmetic(operation) {
+                        Some(asm_code) => asm_result.push_str(&asm_code),
+                        None => return Err(self.invalid_command(*line, command)),
+                    },
+                    CommandType::Label(label) => {
+                        asm_result.push_str(&format!("({})\n", self.scoped_label(label)));
+                    },
+                    CommandType::Goto(label) => {
+                        let label = self.scoped_label(label);
+                        asm_result.push_str(&format!(
+                            "@{label}\n\
+                            0;JMP\n"
+                        ));
+                    },
+                    CommandType::If(label) => {
+                        let label = self.scoped_label(label);
+                        asm_result.push_str(&format!(
+                            "@SP\n\
+                            AM=M-1\n\
+                            D=M\n\
+                            @{label}\n\
+                            D;JNE\n"
+                        ));
+                    },
+                    CommandType::Function(name, num_locals) => {
+                        self.function_name = Some(name.clone());
+                        asm_result.push_str(&format!("({name})\n"));
+                        if let Some(address) = self.profile_counters.and_then(|counters| counters.get(name)) {
+                            asm_result.push_str(&format!(
+                                "@{address}\n\
+                                M=M+1\n"
+                            ));
+                        }
+                        let locals = self.format_locals(*num_locals);
+                        asm_result.push_str(&locals);
+                        asm_result.push_str(&self.format_stack_check("$$STACK_OVERFLOW_FUNCTION"));
+                    },
+                    CommandType::Return => {
+                        if options.optimize_size {
+                            asm_result.push_str(
+                                "@$$RETURN\n\
+                                0;JMP\n"
+                            );
+                        } else {
+                            asm_result.push_str(RETURN_ASM);
+                        }
+                    },
+                    CommandType::Call(name, num_args) if options.optimize_size => {
+                        let return_label = self.unique_label("RETURN_LABEL");
+                        asm_result.push_str(&format!(
+                            "@{num_args}\n\
+                            D=A\n\
+                            @R13\n\
+                            M=D\n\
+                            @{name}\n\
+                            D=A\n\
+                            @R14\n\
+                            M=D\n\
+                            @{return_label}\n\
+                            D=A\n\
+                            @$$CALL\n\
+                            0;JMP\n\
+                            ({return_label})\n"
+                        ));
+                    },
+                    CommandType::Call(name, num_args) => {
+                        let return_label = self.unique_label("RETURN_LABEL"); // Generate a unique return label
+                        asm_result.push_str(&format!(
+                            "@{return_label}\n\
+                            D=A\n\
+                            @SP\n\
+                            A=M\n\
+                            M=D\n\
+                            @SP\n\
+                            M=M+1\n\
+                            @LCL\n\
+                            D=M\n\
+                            @SP\n\
+                            A=M\n\
+                            M=D\n\
+                            @SP\n\
+                            M=M+1\n\
+                            @ARG\n\
+                            D=M\n\
+                            @SP\n\
+                            A=M\n\
+                            M=D\n\
+                            @SP\n\
+                            M=M+1\n\
+                            @THIS\n\
+                            D=M\n\
+                            @SP\n\
+                            A=M\n\
+                            M=D\n\
+                            @SP\n\
+                            M=M+1\n\
+                            @THAT\n\
+                            D=M\n\
+                            @SP\n\
+                            A=M\n\
+                            M=D\n\
+                            @SP\n\
+                            M=M+1\n\
+                            @SP\n\
+                            D=M\n\
+                            @5\n\
+                            D=D-A\n\
+                            @{num_args}\n\
+                            D=D-A\n\
+                            @ARG\n\
+                            M=D\n\
+                            @SP\n\
+                            D=M\n\
+                            @LCL\n\
+                            M=D\n\
+                            {stack_check}\
+                            @{name}\n\
+                            0;JMP\n\
+                            ({return_label})\n",
+                            stack_check = self.format_stack_check("$$STACK_OVERFLOW_CALL")
+                        ));
+                    },
+                }
+            }
+            let emitted = asm_result[block_start..].lines().count();
+            source_map.extend(std::iter::repeat_n(*line, emitted));
+        }
+        let spill = self.spill();
+        if let Some(last) = parsed_content.last() {
+            source_map.extend(std::iter::repeat_n(last.line, spill.lines().count()));
+        }
+        asm_result.push_str(spill);
+        Ok((asm_result, source_map))
+    }
+
+    // `None` for `constant`, which has no address, and for invalid segments or indexes.
+    fn locate(&self, segment: &str, index: i16) -> Option<Location> {
+        let location = match segment {
+            _ if index < 0 => return None,
+            "local" => Location::Indirect("LCL", index),
+            "argument" => Location::Indirect("ARG", index),
+            "this" => Location::Indirect("THIS", index),
+            "that" => Location::Indirect("THAT", index),
+            "temp" if index < 8 => Location::Direct((5 + index).to_string()),
+            "pointer" if index < 2 => Location::Direct(if index == 0 { "THIS" } else { "THAT" }.to_string()),
+            "static" => Location::Direct(format!("{}.{}", self.module_name, index)),
+            _ => return None,
+        };
+        Some(location)
+    }
+
+    // Loads the value of a segment entry into D.
+    fn format_load(&self, segment: &str, index: i16) -> Option<String> {
+        if segment == "constant" {
+            return (index >= 0).then(|| format!(
+                "@{index}\n\
+                D=A\n"
+            ));
+        }
+        let asm_code = match self.locate(segment, index)? {
+            Location::Direct(symbol) => format!(
+                "@{symbol}\n\
+                D=M\n"
+            ),
+            Location::Indirect(base, index) => format!(
+                "@{index}\n\
+                D=A\n\
+                @{base}\n\
+                A=M+D\n\
+                D=M\n"
+            ),
+        };
+        Some(asm_code)
+    }
+
+    // Pushes D. The optimized form bumps SP first and stores through `M-1`, which saves
+    // an instruction.
+    fn push_d(&self) -> &'static str {
+        if self.options.optimize {
+            "@SP\n\
+            M=M+1\n\
+            A=M-1\n\
+            M=D\n"
+        } else {
+            "@SP\n\
+            A=M\n\
+            M=D\n\
+            @SP\n\
+            M=M+1\n"
+        }
+    }
+
+    // Specialized code for command sequences that the one-command-at-a-time translation
+    // handles poorly. Returns the code and how many commands it covers.
+    fn format_fused(&self, commands: &[VmCommand]) -> Option<(String, usize)> {
+        let first = &commands.first()?.command;
+        let second = commands.get(1).map(|command| &command.command);
+        let fused = match (first, second) {
+            (CommandType::Push(segment, 1), Some(CommandType::Arithmetic(operation)))
+                if segment == "constant" && (operation == "add" || operation == "sub") =>
+            {
+                let operator = if operation == "add" { '+' } else { '-' };
+                (format!(
+                    "@SP\n\
+                    A=M-1\n\
+                    M=M{operator}1\n"
+                ), 2)
+            },
+            (CommandType::Push(segment, constant), Some(CommandType::Arithmetic(operation)))
+                if segment == "constant" && *constant >= 0 && (operation == "add" || operation == "sub") =>
+            {
+                let computation = if operation == "add" { "D+M" } else { "M-D" };
+                (format!(
+                    "@{constant}\n\
+                    D=A\n\
+                    @SP\n\
+                    A=M-1\n\
+                    M={computation}\n"
+                ), 2)
+            },
+            (CommandType::Push(segment, 1), Some(CommandType::Arithmetic(operation)))
+                if segment == "constant" && operation == "neg" =>
+            {
+                (String::from(
+                    "@SP\n\
+                    M=M+1\n\
+                    A=M-1\n\
+                    M=-1\n"
+                ), 2)
+            },
+            (CommandType::Push(segment, constant), Some(CommandType::Arithmetic(operation)))
+                if segment == "constant" && *constant >= 0 && operation == "neg" =>
+            {
+                (format!(
+                    "@{constant}\n\
+                    D=-A\n\
+                    {}",
+                    self.push_d()
+                ), 2)
+            },
+            (CommandType::Push(segment, constant @ (0 | 1)), _) if segment == "constant" => {
+                (format!(
+                    "@SP\n\
+                    M=M+1\n\
+                    A=M-1\n\
+                    M={constant}\n"
+                ), 1)
+            },
+            (CommandType::Push(source, source_index), Some(CommandType::Pop(target, target_index))) => {
+                let location = self.locate(target, *target_index)?;
+                let load = self.format_load(source, *source_index)?;
+                if source == target && source_index == target_index {
+                    (String::new(), 2)
+                } else {
+                    (format_move(&load, &location), 2)
+                }
+            },
+            _ => return None,
+        };
+        Some(fused)
+    }
+
+    // Code for commands that can work on a top of stack cached in D (see `cache_tos`),
+    // keeping `tos_in_d` up to date. `None` means the command needs the stack in memory.
+    fn format_cached(&mut self, commands: &[VmCommand]) -> Option<(String, usize)> {
+        let first = &commands.first()?.command;
+        let second = commands.get(1).map(|command| &command.command);
+        let cached = match (first, second) {
+            (CommandType::Push(segment, constant), Some(CommandType::Arithmetic(operation)))
+                if self.tos_in_d && segment == "constant" && *constant >= 0 && (operation == "add" || operation == "sub") =>
+            {
+                let operator = if operation == "add" { '+' } else { '-' };
+                let asm_code = if *constant == 1 {
+                    format!("D=D{operator}1\n")
+                } else {
+                    format!(
+                        "@{constant}\n\
+                        D=D{operator}A\n"
+                    )
+                };
+                (asm_code, 2)
+            },
+            (CommandType::Push(segment, index), _) => {
+                let load = match (segment.as_str(), index) {
+                    ("constant", 0 | 1) => format!("D={index}\n"),
+                    _ => self.format_load(segment, *index)?,
+                };
+                let spill = self.spill();
+                self.tos_in_d = true;
+                (format!("{spill}{load}"), 1)
+            },
+            (CommandType::Pop(segment, index), _) if self.tos_in_d => {
+                let location = self.locate(segment, *index)?;
+                self.tos_in_d = false;
+                (format_store(&location), 1)
+            },
+            (CommandType::Arithmetic(operation), _) if self.tos_in_d => {
+                let computation = match operation.as_str() {
+                    "add" => "D=D+M",
+                    "sub" => "D=M-D",
+                    "and" => "D=D&M",
+                    "or" => "D=D|M",
+                    "neg" => return Some((String::from("D=-D\n"), 1)),
+                    "not" => return Some((String::from("D=!D\n"), 1)),
+                    _ => return None,
+                };
+                (format!(
+                    "@SP\n\
+                    AM=M-1\n\
+                    {computation}\n"
+                ), 1)
+            },
+            (CommandType::If(label), _) if self.tos_in_d => {
+                self.tos_in_d = false;
+                (format!(
+                    "@{}\n\
+                    D;JNE\n",
+                    self.scoped_label(label)
+                ), 1)
+            },
+            _ => return None,
+        };
+        Some(cached)
+    }
+
+    // `call f n; return`: f gets the current function's frame instead of a new one on top
+    // of it. The saved frame (return address, LCL, ARG, THIS, THAT of our caller) is pushed
+    // after the n arguments, those n + 5 words are copied down to ARG[0], and SP and LCL are
+    // set past them as `call` would have done. f's `return` then goes straight back to our
+    // caller with the same stack and registers a normal call and return would leave.
+    fn format_tail_call(&mut self, commands: &[VmCommand]) -> Option<(String, usize)> {
+        let (CommandType::Call(name, num_args), Some(CommandType::Return)) =
+            (&commands.first()?.command, commands.get(1).map(|command| &command.command))
+        else {
+            return None;
+        };
+        let mut asm_code = String::new();
+        for offset in (1..=5).rev() {
+            asm_code.push_str(&format!(
+                "@LCL\n\
+                D=M\n\
+                @{offset}\n\
+                A=D-A\n\
+                D=M\n\
+                @SP\n\
+                M=M+1\n\
+                A=M-1\n\
+                M=D\n"
+            ));
+        }
+        let words = num_args + 5;
+        let copy_label = self.unique_label("TAIL_COPY");
+        asm_code.push_str(&format!(
+            "@SP\n\
+            D=M\n\
+            @{words}\n\
+            D=D-A\n\
+            @R13\n\
+            M=D\n\
+            @ARG\n\
+            D=M\n\
+            @R14\n\
+            M=D\n\
+            @{words}\n\
+            D=A\n\
+            @R15\n\
+            M=D\n\
+            ({copy_label})\n\
+            @R13\n\
+            A=M\n\
+            D=M\n\
+            @R14\n\
+            A=M\n\
+            M=D\n\
+            @R13\n\
+            M=M+1\n\
+            @R14\n\
+            M=M+1\n\
+            @R15\n\
+            MD=M-1\n\
+            @{copy_label}\n\
+            D;JGT\n\
+            @R14\n\
+            D=M\n\
+            @SP\n\
+            M=D\n\
+            @LCL\n\
+            M=D\n\
+            {stack_check}\
+            @{name}\n\
+            0;JMP\n",
+            stack_check = self.format_stack_check("$$STACK_OVERFLOW_CALL")
+        ));
+        Some((asm_code, 2))
+    }
+
+    // Pushes `num_locals` zeros for a function's locals, updating SP once.
+    fn format_locals(&mut self, num_locals: usize) -> String {
+        let unrolled = if self.options.optimize_size { UNROLLED_LOCALS_SIZE } else { UNROLLED_LOCALS };
+        match num_locals {
+            0 => String::new(),
+            1 => String::from(
+                "@SP\n\
+                A=M\n\
+                M=0\n\
+                @SP\n\
+                M=M+1\n"
+            ),
+            _ if num_locals <= unrolled => {
+                let mut asm_code = String::from(
+                    "@SP\n\
+                    A=M\n\
+                    M=0\n"
+                );
+                for _ in 1..num_locals {
+                    asm_code.push_str(
+                        "A=A+1\n\
+                        M=0\n"
+                    );
+                }
+                asm_code.push_str(
+                    "D=A+1\n\
+                    @SP\n\
+                    M=D\n"
+                );
+                asm_code
+            },
+            _ => {
+                let label = self.unique_label("LOCALS");
+                format!(
+                    "@{num_locals}\n\
+                    D=A\n\
+                    ({label})\n\
+                    @SP\n\
+                    AM=M+1\n\
+                    A=A-1\n\
+                    M=0\n\
+                    D=D-1\n\
+                    @{label}\n\
+                    D;JGT\n"
+                )
+            },
+        }
+    }
+
+    // With `stack_limit`, jumps to `handler` when SP is above the limit; clobbers D.
+    fn format_stack_check(&self, handler: &str) -> String {
+        match self.options.stack_limit {
+            Some(limit) => format_stack_check(limit, handler),
+            None => String::new(),
+        }
+    }
+
+    // Pushes a top of stack cached in D, if there is one.
+    fn spill(&mut self) -> &'static str {
+        if !self.tos_in_d {
+            return "";
+        }
+        self.tos_in_d = false;
+        "@SP\n\
+        M=M+1\n\
+        A=M-1\n\
+        M=D\n"
+    }
+
+    fn invalid_command(&self, line: usize, command: &CommandType) -> Error {
+        Error::InvalidCommand { module: self.module_name.clone(), line, command: command.to_string() }
+    }
+
+    fn format_arithmetic(&mut self, operation: &str) -> Option<String> {
+        let asm_code = match operation {
+            "add" => {
+                String::from(
+                    "@SP\n\
+                    AM=M-1\n\
+                    D=M\n\
+                    A=A-1\n\
+                    M=D+M\n"
+                )
+            },
+            "sub" => {
+                String::from(
+                    "@SP\n\
+                    AM=M-1\n\
+                    D=M\n\
+                    A=A-1\n\
+                    M=M-D\n"
+                )
+            },
+            "neg" => {
+                String::from(
+                    "@SP\n\
+                    A=M-1\n\
+                    M=-M\n"
+                )
+            },
+            "eq" => format_comparison("JEQ", &self.unique_label("EQ"), self.options),
+            "gt" => format_comparison("JGT", &self.unique_label("GT"), self.options),
+            "lt" => format_comparison("JLT", &self.unique_label("LT"), self.options),
+            "and" => {
+                String::from(
+                    "@SP\n\
+                    AM=M-1\n\
+                    D=M\n\
+                    A=A-1\n\
+                    M=D&M\n"
+                )
+            },
+            "or" => {
+                String::from(
+                    "@SP\n\
+                    AM=M-1\n\
+                    D=M\n\
+                    A=A-1\n\
+                    M=D|M\n"
+                )
+            },
+            "not" => {
+                String::from(
+                    "@SP\n\
+                    A=M-1\n\
+                    M=!M\n"
+                )
+            },
+            _ if self.options.extensions && is_extension(operation) => self.format_routine_call(operation),
+            _ => return None,
+        };
+        Some(asm_code)
+    }
+
+    // Calls the shared routine `$$<OPERATION>` with the return address in D.
+    fn format_routine_call(&mut self, operation: &str) -> String {
+        let return_label = self.unique_label("RETURN_LABEL");
+        format!(
+            "@{return_label}\n\
+            D=A\n\
+            @$${}\n\
+            0;JMP\n\
+            ({return_label})\n",
+            operation.to_uppercase()
+        )
+    }
+}
+
+// Restores the caller's frame and jumps back to its return address.
+const RETURN_ASM: &str =
+    "@LCL\n\
+    D=M\n\
+    @R14\n\
+    M=D\n\
+    @5\n\
+    A=D-A\n\
+    D=M\n\
+    @R15\n\
+    M=D\n\
+    @SP\n\
+    A=M-1\n\
+    D=M\n\
+    @ARG\n\
+    A=M\n\
+    M=D\n\
+    D=A+1\n\
+    @SP\n\
+    M=D\n\
+    @R14\n\
+    AM=M-1\n\
+    D=M\n\
+    @THAT\n\
+    M=D\n\
+    @R14\n\
+    AM=M-1\n\
+    D=M\n\
+    @THIS\n\
+    M=D\n\
+    @R14\n\
+    AM=M-1\n\
+    D=M\n\
+    @ARG\n\
+    M=D\n\
+    @R14\n\
+    AM=M-1\n\
+    D=M\n\
+    @LCL\n\
+    M=D\n\
+    @R15\n\
+    A=M\n\
+    0;JMP\n";
+
+// Pops the top of the stack into a segment entry.
+fn format_pop(location: &Location) -> String {
+    match location {
+        Location::Direct(symbol) => format!(
+            "@SP\n\
+            AM=M-1\n\
+            D=M\n\
+            @{symbol}\n\
+            M=D\n"
+        ),
+        Location::Indirect(base, index) => format!(
+            "@{index}\n\
+            D=A\n\
+            @{base}\n\
+            D=M+D\n\
+            @R13\n\
+            M=D\n\
+            @SP\n\
+            AM=M-1\n\
+            D=M\n\
+            @R13\n\
+            A=M\n\
+            M=D\n"
+        ),
+    }
+}
+
+// `push X; pop Y` without going through the stack: runs `load` (which leaves X in D)
+// and stores D into Y. Small offsets from a base pointer are reached with `A=A+1`,
+// larger ones need the address computed into R13 before `load` clobbers D.
+fn format_move(load: &str, location: &Location) -> String {
+    match location {
+        Location::Direct(symbol) => format!(
+            "{load}\
+            @{symbol}\n\
+            M=D\n"
+        ),
+        Location::Indirect(base, index) if *index <= 3 => format!(
+            "{load}\
+            @{base}\n\
+            A=M\n\
+            {}\
+            M=D\n",
+            "A=A+1\n".repeat(*index as usize)
+        ),
+        Location::Indirect(base, index) => format!(
+            "@{index}\n\
+            D=A\n\
+            @{base}\n\
+            D=M+D\n\
+            @R13\n\
+            M=D\n\
+            {load}\
+            @R13\n\
+            A=M\n\
+            M=D\n"
+        ),
+    }
+}
+
+// Stores D into a segment entry. Large offsets from a base pointer need both the value
+// and the address, so the value is parked in R13 while the address is computed.
+fn format_store(location: &Location) -> String {
+    match location {
+        Location::Indirect(base, index) if *index > 3 => format!(
+            "@R13\n\
+            M=D\n\
+            @{index}\n\
+            D=A\n\
+            @{base}\n\
+            D=M+D\n\
+            @R14\n\
+            M=D\n\
+            @R13\n\
+            D=M\n\
+            @R14\n\
+            A=M\n\
+            M=D\n"
+        ),
+        _ => format_move("", location),
+    }
+}
+
+// Sets SP to 256 and calls `Sys.init`, as the VM specification requires for whole programs.
+// With `comments` the block is marked `// bootstrap`: the call has no VM source line.
+pub(crate) fn format_bootstrap(options: &Options) -> String {
+    let mut bootstrap = String::new();
+    if options.comments {
+        bootstrap.push_str("// bootstrap\n");
+    }
+    bootstrap.push_str(
+        "@256\n\
+        D=A\n\
+        @SP\n\
+        M=D\n"
+    );
+    let call = VmCommand { line: 0, command: CommandType::Call("Sys.init".to_string(), 0) };
+    let call_options = Options { comments: false, ..options.clone() };
+    let (call_asm, _) = Translator::new("Bootstrap", &call_options, None)
+        .convert_to_asm(&[call])
+        .expect("a call is always translatable");
+    bootstrap.push_str(&call_asm);
+    bootstrap
+}
+
+// The arithmetic commands enabled by `extensions`, all binary like `add`.
+const EXTENSIONS: [&str; 5] = ["mult", "div", "mod", "shl", "shr"];
+
+fn is_extension(operation: &str) -> bool {
+    EXTENSIONS.contains(&operation)
+}
+
+fn is_comparison(operation: &str) -> bool {
+    matches!(operation, "eq" | "gt" | "lt")
+}
+
+// Pops y and x, then replaces x with -1 (true) if `x - y` satisfies `jump`, else 0.
+// `x - y` overflows when the operands have different signs (e.g. 20000 and -20000),
+// so by default the signs are checked first and the difference is only computed
+// when they match; otherwise the sign of x alone decides the result.
+fn format_comparison(jump: &str, label: &str, options: &Options) -> String {
+    if options.unchecked_compare {
+        return format!(
+            "@SP\n\
+            AM=M-1\n\
+            D=M\n\
+            A=A-1\n\
+            D=M-D\n\
+            M=-1\n\
+            @{label}\n\
+            D;{jump}\n\
+            @SP\n\
+            A=M-1\n\
+            M=0\n\
+            ({label})\n"
+        );
+    }
+    format!(
+        "@SP\n\
+        AM=M-1\n\
+        D=M\n\
+        @R13\n\
+        M=D\n\
+        @SP\n\
+        A=M-1\n\
+        D=M\n\
+        @{label}_XNEG\n\
+        D;JLT\n\
+        @R13\n\
+        D=M\n\
+        @{label}_SUB\n\
+        D;JGE\n\
+        D=1\n\
+        @{label}_TEST\n\
+        0;JMP\n\
+        ({label}_XNEG)\n\
+        @R13\n\
+        D=M\n\
+        @{label}_SUB\n\
+        D;JLT\n\
+        D=-1\n\
+        @{label}_TEST\n\
+        0;JMP\n\
+        ({label}_SUB)\n\
+        @R13\n\
+        D=M\n\
+        @SP\n\
+        A=M-1\n\
+        D=M-D\n\
+        ({label}_TEST)\n\
+        @SP\n\
+        A=M-1\n\
+        M=-1\n\
+        @{label}\n\
+        D;{jump}\n\
+        @SP\n\
+        A=M-1\n\
+        M=0\n\
+        ({label})\n"
+    )
+}
+
+// Shared routines used by the size-optimized mode, placed at the start of the program
+// behind a jump. Call sites enter them with the return address in D (comparisons,
+// `$$CALL`) and `$$CALL` additionally expects nArgs in R13 and the callee in R14.
+pub(crate) fn format_runtime(options: &Options) -> String {
+    let mut runtime = String::from(
+        "@$$START\n\
+        0;JMP\n\
+        ($$CALL)\n\
+        @SP\n\
+        A=M\n\
+        M=D\n\
+        @LCL\n\
+        D=M\n\
+        @SP\n\
+        AM=M+1\n\
+        M=D\n\
+        @ARG\n\
+        D=M\n\
+        @SP\n\
+        AM=M+1\n\
+        M=D\n\
+        @THIS\n\
+        D=M\n\
+        @SP\n\
+        AM=M+1\n\
+        M=D\n\
+        @THAT\n\
+        D=M\n\
+        @SP\n\
+        AM=M+1\n\
+        M=D\n\
+        @SP\n\
+        MD=M+1\n\
+        @LCL\n\
+        M=D\n\
+        @R13\n\
+        D=D-M\n\
+        @5\n\
+        D=D-A\n\
+        @ARG\n\
+        M=D\n"
+    );
+    if let Some(limit) = options.stack_limit {
+        runtime.push_str(&format_stack_check(limit, "$$STACK_OVERFLOW_CALL"));
+    }
+    runtime.push_str(
+        "@R14\n\
+        A=M\n\
+        0;JMP\n\
+        ($$RETURN)\n"
+    );
+    runtime.push_str(RETURN_ASM);
+    for (operation, jump) in [("EQ", "JEQ"), ("GT", "JGT"), ("LT", "JLT")] {
+        runtime.push_str(&format!(
+            "($${operation})\n\
+            @R15\n\
+            M=D\n"
+        ));
+        runtime.push_str(&format_comparison(jump, &format!("$${operation}_DONE"), options));
+        runtime.push_str(
+            "@R15\n\
+            A=M\n\
+            0;JMP\n"
+        );
+    }
+    runtime.push_str("($$START)\n");
+    runtime
+}
+
+fn format_stack_check(limit: u16, handler: &str) -> String {
+    format!(
+        "@SP\n\
+        D=M\n\
+        @{limit}\n\
+        D=D-A\n\
+        @{handler}\n\
+        D;JGT\n"
+    )
+}
+
+// Targets of the stack checks: each stores its error code and halts in an endless loop.
+// Placed at the start of the program behind a jump, like the shared runtime routines.
+pub(crate) fn format_stack_guard() -> String {
+    let mut guard = String::from(
+        "@$$STACK_GUARD_END\n\
+        0;JMP\n"
+    );
+    for (handler, code) in [("$$STACK_OVERFLOW_CALL", STACK_ERROR_CALL), ("$$STACK_OVERFLOW_FUNCTION", STACK_ERROR_FUNCTION)] {
+        guard.push_str(&format!(
+            "({handler})\n\
+            @{code}\n\
+            D=A\n\
+            @{STACK_ERROR_ADDRESS}\n\
+            M=D\n\
+            @$$STACK_HALT\n\
+            0;JMP\n"
+        ));
+    }
+    guard.push_str(
+        "($$STACK_HALT)\n\
+        @$$STACK_HALT\n\
+        0;JMP\n\
+        ($$STACK_GUARD_END)\n"
+    );
+    guard
+}
+
+// The routines behind the `extensions` commands the modules use, behind a jump. Like the
+// `optimize_size` comparisons, each is entered with x and y on the stack and the return
+// address in D, which it keeps in R15, and leaves its result in place of x.
+//
+//   mult: x * y, wrapping around
+//   div:  x / y rounded towards zero, wrapping around for -32768 / -1
+//   mod:  the remainder of div, with the sign of x
+//   shl:  x shifted left by y bits
+//   shr:  x shifted right by y bits, filling with zeros
+//
+// Division by zero gives 0 for both div and mod, and shifts by y <= 0 leave x unchanged.
+pub(crate) fn format_extension_runtime(modules: &[VmModule]) -> String {
+    let uses = |operations: &[&str]| modules.iter()
+        .flat_map(|module| &module.commands)
+        .any(|vm_command| matches!(&vm_command.command, CommandType::Arithmetic(operation) if operations.contains(&operation.as_str())));
+    let mut runtime = String::new();
+    if uses(&["mult"]) {
+        // Adds x, doubled at each step, to the result for every bit set in y.
+        runtime.push_str(
+            "($$MULT)\n\
+            @R15\n\
+            M=D\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            D=M\n\
+            M=0\n\
+            @R13\n\
+            M=D\n\
+            @R14\n\
+            M=1\n\
+            ($$MULT_LOOP)\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            @R14\n\
+            D=D&M\n\
+            @$$MULT_NEXT\n\
+            D;JEQ\n\
+            @R13\n\
+            D=M\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            M=D+M\n\
+            ($$MULT_NEXT)\n\
+            @R13\n\
+            D=M\n\
+            M=D+M\n\
+            @R14\n\
+            D=M\n\
+            MD=D+M\n\
+            @$$MULT_LOOP\n\
+            D;JNE\n\
+            @SP\n\
+            M=M-1\n\
+            @R15\n\
+            A=M\n\
+            0;JMP\n"
+        );
+    }
+    if uses(&["div", "mod"]) {
+        // Long division of |x| by |y| as unsigned numbers, a bit of |x| at a time from
+        // the top. R13 selects the result (0 for div, -1 for mod) and R14 counts the
+        // negative operands that decide its sign. The remainder, quotient and bit count
+        // live in the free words just above the stack.
+        runtime.push_str(
+            "($$DIV)\n\
+            @R15\n\
+            M=D\n\
+            @R13\n\
+            M=0\n\
+            @$$DIVMOD\n\
+            0;JMP\n\
+            ($$MOD)\n\
+            @R15\n\
+            M=D\n\
+            @R13\n\
+            M=-1\n\
+            ($$DIVMOD)\n\
+            @R14\n\
+            M=0\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            D=M\n\
+            @$$DIVMOD_X_POSITIVE\n\
+            D;JGE\n\
+            @R14\n\
+            M=1\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            M=-M\n\
+            ($$DIVMOD_X_POSITIVE)\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            @$$DIVMOD_ZERO\n\
+            D;JEQ\n\
+            @$$DIVMOD_Y_POSITIVE\n\
+            D;JGT\n\
+            @SP\n\
+            A=M-1\n\
+            M=-M\n\
+            @R13\n\
+            D=M\n\
+            @$$DIVMOD_Y_POSITIVE\n\
+            D;JNE\n\
+            @R14\n\
+            M=M+1\n\
+            ($$DIVMOD_Y_POSITIVE)\n\
+            @16\n\
+            D=A\n\
+            @SP\n\
+            A=M\n\
+            M=0\n\
+            A=A+1\n\
+            M=0\n\
+            A=A+1\n\
+            M=D\n\
+            ($$DIVMOD_LOOP)\n\
+            @SP\n\
+            A=M\n\
+            D=M\n\
+            M=D+M\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            D=M\n\
+            M=D+M\n\
+            @$$DIVMOD_SHIFTED\n\
+            D;JGE\n\
+            @SP\n\
+            A=M\n\
+            M=M+1\n\
+            ($$DIVMOD_SHIFTED)\n\
+            @SP\n\
+            A=M+1\n\
+            D=M\n\
+            M=D+M\n\
+            @SP\n\
+            A=M\n\
+            D=M\n\
+            @$$DIVMOD_SUBTRACT\n\
+            D;JLT\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            @$$DIVMOD_NEXT\n\
+            D;JLT\n\
+            @SP\n\
+            A=M\n\
+            D=M\n\
+            A=A-1\n\
+            D=D-M\n\
+            @$$DIVMOD_NEXT\n\
+            D;JLT\n\
+            ($$DIVMOD_SUBTRACT)\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            A=A+1\n\
+            M=M-D\n\
+            A=A+1\n\
+            M=M+1\n\
+            ($$DIVMOD_NEXT)\n\
+            @SP\n\
+            A=M+1\n\
+            A=A+1\n\
+            MD=M-1\n\
+            @$$DIVMOD_LOOP\n\
+            D;JGT\n\
+            @R13\n\
+            D=M\n\
+            @$$DIVMOD_REMAINDER\n\
+            D;JNE\n\
+            @SP\n\
+            A=M+1\n\
+            D=M\n\
+            @$$DIVMOD_RESULT\n\
+            0;JMP\n\
+            ($$DIVMOD_REMAINDER)\n\
+            @SP\n\
+            A=M\n\
+            D=M\n\
+            ($$DIVMOD_RESULT)\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            M=D\n\
+            @R14\n\
+            D=M-1\n\
+            @$$DIVMOD_END\n\
+            D;JNE\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            M=-M\n\
+            @$$DIVMOD_END\n\
+            0;JMP\n\
+            ($$DIVMOD_ZERO)\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            M=0\n\
+            ($$DIVMOD_END)\n\
+            @SP\n\
+            M=M-1\n\
+            @R15\n\
+            A=M\n\
+            0;JMP\n"
+        );
+    }
+    if uses(&["shl"]) {
+        // Doubles x y times, stopping early once it is 0.
+        runtime.push_str(
+            "($$SHL)\n\
+            @R15\n\
+            M=D\n\
+            ($$SHL_LOOP)\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            @$$SHL_END\n\
+            D;JLE\n\
+            @SP\n\
+            A=M-1\n\
+            M=M-1\n\
+            A=A-1\n\
+            D=M\n\
+            MD=D+M\n\
+            @$$SHL_LOOP\n\
+            D;JNE\n\
+            ($$SHL_END)\n\
+            @SP\n\
+            M=M-1\n\
+            @R15\n\
+            A=M\n\
+            0;JMP\n"
+        );
+    }
+    if uses(&["shr"]) {
+        // Copies bit y + i of x to bit i of the result, which takes the place of y, with
+        // R13 holding the source bit and R14 the destination bit.
+        runtime.push_str(
+            "($$SHR)\n\
+            @R15\n\
+            M=D\n\
+            @R13\n\
+            M=1\n\
+            ($$SHR_SOURCE)\n\
+            @SP\n\
+            A=M-1\n\
+            D=M\n\
+            @$$SHR_COPY\n\
+            D;JLE\n\
+            @SP\n\
+            A=M-1\n\
+            M=M-1\n\
+            @R13\n\
+            D=M\n\
+            MD=D+M\n\
+            @$$SHR_SOURCE\n\
+            D;JNE\n\
+            ($$SHR_COPY)\n\
+            @R14\n\
+            M=1\n\
+            @SP\n\
+            A=M-1\n\
+            M=0\n\
+            ($$SHR_LOOP)\n\
+            @R13\n\
+            D=M\n\
+            @$$SHR_END\n\
+            D;JEQ\n\
+            @SP\n\
+            A=M-1\n\
+            A=A-1\n\
+            D=D&M\n\
+            @$$SHR_NEXT\n\
+            D;JEQ\n\
+            @R14\n\
+            D=M\n\
+            @SP\n\
+            A=M-1\n\
+            M=D|M\n\
+            ($$SHR_NEXT)\n\
+            @R13\n\
+            D=M\n\
+            M=D+M\n\
+            @R14\n\
+            D=M\n\
+            M=D+M\n\
+            @$$SHR_LOOP\n\
+            0;JMP\n\
+            ($$SHR_END)\n\
+            @SP\n\
+            AM=M-1\n\
+            D=M\n\
+            A=A-1\n\
+            M=D\n\
+            @R15\n\
+            A=M\n\
+            0;JMP\n"
+        );
+    }
+    if runtime.is_empty() {
+        return runtime;
+    }
+    format!(
+        "@$$EXTENSIONS_END\n\
+        0;JMP\n\
+        {runtime}\
+        ($$EXTENSIONS_END)\n"
+    )
+}
+
+/// Counts real instructions, skipping comments, blank lines and label declarations.
+pub fn count_instructions(asm: &str) -> usize {
+    asm.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//") && !line.starts_with('('))
+        .count()
+}
+
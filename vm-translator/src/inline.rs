@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::analysis::stack_effect;
+use crate::parser::{CommandType, VmCommand, VmModule};
+
+// Temp registers available to hold the arguments, locals and saved pointers of an
+// inlined function (`temp 0` to `temp 7`).
+const TEMP_SLOTS: usize = 8;
+
+// A function that can be expanded at its call sites.
+struct LeafFunction {
+    module: String,
+    num_locals: usize,
+    body: Vec<CommandType>, // without the `function` line and the final `return`
+    max_argument: Option<i16>,
+    writes_pointer: [bool; 2],
+    uses_static: bool,
+}
+
+/// Replaces `call f n` with the body of `f` when `f` is a small leaf function: at most
+/// `limit` commands, straight-line (no labels, jumps or calls), ending in its only
+/// `return` and leaving just the returned value on the stack. The arguments and locals
+/// of the expanded body live in temp slots the calling function does not use itself,
+/// and THIS/THAT are saved around it when `f` writes `pointer`, so the stack and the
+/// caller's registers end up as after a real call. Functions that use the temp segment
+/// themselves, or statics of another module, are never inlined.
+pub fn inline_leaf_functions(modules: &[VmModule], limit: usize) -> Vec<VmModule> {
+    let leaf_functions = find_leaf_functions(modules, limit);
+    modules.iter()
+        .map(|module| {
+            let mut commands = Vec::new();
+            let mut free_slots = Vec::new();
+            for (position, vm_command) in module.commands.iter().enumerate() {
+                if position == 0 || matches!(vm_command.command, CommandType::Function(..)) {
+                    free_slots = free_temp_slots(&module.commands[position..]);
+                }
+                let expansion = match &vm_command.command {
+                    CommandType::Call(name, num_args) => leaf_functions.get(name)
+                        .and_then(|function| expand(function, &module.name, *num_args, &free_slots)),
+                    _ => None,
+                };
+                match expansion {
+                    Some(expansion) => commands.extend(expansion.into_iter()
+                        .map(|command| VmCommand { line: vm_command.line, command })),
+                    None => commands.push(vm_command.clone()),
+                }
+            }
+            VmModule { name: module.name.clone(), commands }
+        })
+        .collect()
+}
+
+// The temp slots never used by the function starting at `commands[0]` (or by the code
+// before the first function), which inlined calls can take without clobbering its values.
+fn free_temp_slots(commands: &[VmCommand]) -> Vec<usize> {
+    let mut used = [false; TEMP_SLOTS];
+    let function = commands[1..].iter()
+        .take_while(|command| !matches!(command.command, CommandType::Function(..)));
+    for command in std::iter::once(&commands[0]).chain(function) {
+        if let CommandType::Push(segment, index) | CommandType::Pop(segment, index) = &command.command {
+            if segment == "temp" && (0..TEMP_SLOTS as i16).contains(index) {
+                used[*index as usize] = true;
+            }
+        }
+    }
+    (0..TEMP_SLOTS).filter(|slot| !used[*slot]).collect()
+}
+
+fn find_leaf_functions(modules: &[VmModule], limit: usize) -> HashMap<String, LeafFunction> {
+    let mut leaf_functions = HashMap::new();
+    for module in modules {
+        let mut position = 0;
+        while position < module.commands.len() {
+            let CommandType::Function(name, num_locals) = &module.commands[position].command else {
+                position += 1;
+                continue;
+            };
+            let end = module.commands[position + 1..].iter()
+                .position(|command| matches!(command.command, CommandType::Function(..)))
+                .map_or(module.commands.len(), |offset| position + 1 + offset);
+            let commands: Vec<&CommandType> = module.commands[position + 1..end].iter()
+                .map(|command| &command.command)
+                .collect();
+            if let Some(function) = leaf_function(&module.name, *num_locals, &commands, limit) {
+                leaf_functions.insert(name.clone(), function);
+            }
+            position = end;
+        }
+    }
+    leaf_functions
+}
+
+fn leaf_function(module: &str, num_locals: usize, commands: &[&CommandType], limit: usize) -> Option<LeafFunction> {
+    let (CommandType::Return, body) = commands.split_last()? else {
+        return None;
+    };
+    if body.len() > limit {
+        return None;
+    }
+    let mut function = LeafFunction {
+        module: module.to_string(),
+        num_locals,
+        body: Vec::new(),
+        max_argument: None,
+        writes_pointer: [false; 2],
+        uses_static: false,
+    };
+    // The body runs on top of the caller's stack, so it must not pop below where it
+    // started, and `return` must find exactly one value, as a real return keeps only
+    // that one.
+    let mut height = 0usize;
+    for command in body {
+        let (popped, pushed) = stack_effect(command);
+        height = height.checked_sub(popped)? + pushed;
+        match command {
+            CommandType::Push(segment, index) | CommandType::Pop(segment, index) => match segment.as_str() {
+                "temp" => return None,
+                "argument" => function.max_argument = function.max_argument.max(Some(*index)),
+                "local" if *index as usize >= num_locals => return None,
+                "static" => function.uses_static = true,
+                "pointer" if matches!(command, CommandType::Pop(..)) && (0..2).contains(index) => {
+                    function.writes_pointer[*index as usize] = true;
+                },
+                _ => {},
+            },
+            CommandType::Arithmetic(_) => {},
+            _ => return None,
+        }
+        function.body.push((*command).clone());
+    }
+    if height != 1 {
+        return None;
+    }
+    Some(function)
+}
+
+// The commands replacing `call function num_args` inside `caller_module`, keeping
+// arguments, locals and saved pointers in `slots`, in that order.
+fn expand(function: &LeafFunction, caller_module: &str, num_args: usize, slots: &[usize]) -> Option<Vec<CommandType>> {
+    let saved_pointers = function.writes_pointer.iter().filter(|written| **written).count();
+    if num_args + function.num_locals + saved_pointers > slots.len()
+        || function.max_argument.is_some_and(|index| index as usize >= num_args)
+        || (function.uses_static && function.module != caller_module)
+    {
+        return None;
+    }
+    let push = |segment: &str, index: usize| CommandType::Push(segment.to_string(), index as i16);
+    let pop = |segment: &str, index: usize| CommandType::Pop(segment.to_string(), index as i16);
+    let push_temp = |slot: usize| push("temp", slots[slot]);
+    let pop_temp = |slot: usize| pop("temp", slots[slot]);
+    let mut commands = Vec::new();
+    // The last argument is on top of the stack.
+    for index in (0..num_args).rev() {
+        commands.push(pop_temp(index));
+    }
+    for index in 0..function.num_locals {
+        commands.push(push("constant", 0));
+        commands.push(pop_temp(num_args + index));
+    }
+    let mut saved_slot = num_args + function.num_locals;
+    let mut restores = Vec::new();
+    for (pointer, written) in function.writes_pointer.iter().enumerate() {
+        if *written {
+            commands.push(push("pointer", pointer));
+            commands.push(pop_temp(saved_slot));
+            restores.push(push_temp(saved_slot));
+            restores.push(pop("pointer", pointer));
+            saved_slot += 1;
+        }
+    }
+    for command in &function.body {
+        let command = match command {
+            CommandType::Push(segment, index) if segment == "argument" => push_temp(*index as usize),
+            CommandType::Pop(segment, index) if segment == "argument" => pop_temp(*index as usize),
+            CommandType::Push(segment, index) if segment == "local" => push_temp(num_args + *index as usize),
+            CommandType::Pop(segment, index) if segment == "local" => pop_temp(num_args + *index as usize),
+            _ => command.clone(),
+        };
+        commands.push(command);
+    }
+    commands.extend(restores);
+    Some(commands)
+}
@@ -0,0 +1,183 @@
+//! Hack VM translator (projects 7 and 8): turns parsed `.vm` modules into Hack assembly.
+
+use std::collections::HashMap;
+use std::fmt;
+
+mod analysis;
+mod bytecode;
+mod callgraph;
+mod codegen;
+mod inline;
+mod optimizer;
+mod parser;
+mod paths;
+
+use codegen::Translator;
+
+pub use analysis::{analyze, worst_case, FunctionAnalysis, WorstCase};
+pub use bytecode::{read_bytecode, write_bytecode, BYTECODE_VERSION};
+pub use callgraph::{eliminate_dead_functions, CallGraph};
+pub use codegen::{
+    count_instructions, Options, DEFAULT_INLINE_LIMIT, DEFAULT_STACK_LIMIT, PROFILE_BASE, STACK_ERROR_ADDRESS,
+    STACK_ERROR_CALL, STACK_ERROR_FUNCTION,
+};
+pub use inline::inline_leaf_functions;
+pub use optimizer::optimize;
+pub use parser::{parse, parse_file, CommandType, VmCommand, VmModule};
+pub use paths::{expand_inputs, is_same_file, output_path};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A line that is neither a comment nor a well-formed VM command.
+    Syntax { file: String, line: usize, text: String },
+    /// A well-formed command that cannot be translated, e.g. `pop constant 0` or `push temp 9`.
+    InvalidCommand { module: String, line: usize, command: String },
+    /// A bytecode file that is truncated, corrupt or of another format version.
+    Bytecode { file: String, offset: usize, message: String },
+    /// More functions than profile counters between `PROFILE_BASE` and `STACK_ERROR_ADDRESS`.
+    TooManyProfiledFunctions { functions: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { file, line, text } => write!(f, "{}:{}: cannot parse `{}`", file, line, text),
+            Error::InvalidCommand { module, line, command } => write!(f, "{}.vm:{}: invalid command `{}`", module, line, command),
+            Error::Bytecode { file, offset, message } => write!(f, "{}: byte {}: {}", file, offset, message),
+            Error::TooManyProfiledFunctions { functions } => write!(
+                f,
+                "{} functions do not fit in the {} profile counters",
+                functions,
+                STACK_ERROR_ADDRESS - PROFILE_BASE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The result of [`translate_with_options`].
+pub struct Translation {
+    pub asm: String,
+    /// For every line of `asm`, the index of the module and the VM line it was generated
+    /// from; `None` for the bootstrap and the shared runtime routines.
+    pub source_map: Vec<Option<(usize, usize)>>,
+    /// With `options.profile`, the RAM address of each function's call counter, in the
+    /// order the functions are defined. Functions removed as unreachable have none.
+    pub profile: Vec<(u16, String)>,
+}
+
+impl Translation {
+    /// The source map as text, one line per line of `asm`: `<asm line> <vm file>:<vm line>`,
+    /// both 1-based, or `<asm line> -` for the bootstrap and runtime code. `modules` are
+    /// the ones that were translated.
+    pub fn format_source_map(&self, modules: &[VmModule]) -> String {
+        self.source_map.iter()
+            .enumerate()
+            .map(|(asm_line, location)| match location {
+                Some((module, vm_line)) => format!("{} {}.vm:{}\n", asm_line + 1, modules[*module].name, vm_line),
+                None => format!("{} -\n", asm_line + 1),
+            })
+            .collect()
+    }
+}
+
+/// Translates the modules of a program, in order, with the default options.
+pub fn translate(modules: &[VmModule]) -> Result<String, Error> {
+    translate_with_options(modules, &Options::default()).map(|translation| translation.asm)
+}
+
+/// Translates the modules of a program, in order. When one of them defines `Sys.init`
+/// the output starts with the bootstrap code that calls it. The modules first go through
+/// [`inline_leaf_functions`] when `options.inline_limit` is non-zero and
+/// `options.profile` is off, then, with
+/// `options.eliminate_dead_functions`, only the functions reachable from `Sys.init` are
+/// kept, and with `options.optimize` every module goes through [`optimize`].
+///
+/// Each module is optimized and translated on its own thread. Labels are numbered per
+/// module, so the output is the same as a sequential translation.
+pub fn translate_with_options(modules: &[VmModule], options: &Options) -> Result<Translation, Error> {
+    let defines_sys_init = modules.iter()
+        .flat_map(|module| &module.commands)
+        .any(|command| matches!(&command.command, CommandType::Function(name, _) if name == "Sys.init"));
+    let inlined: Vec<VmModule>;
+    // Inlined calls would never reach the counter at the start of the function.
+    let modules = if options.inline_limit > 0 && !options.profile {
+        inlined = inline_leaf_functions(modules, options.inline_limit);
+        &inlined[..]
+    } else {
+        modules
+    };
+    let live: Vec<VmModule>;
+    let modules = if options.eliminate_dead_functions && defines_sys_init {
+        live = eliminate_dead_functions(modules, "Sys.init");
+        &live[..]
+    } else {
+        modules
+    };
+    let profile = if options.profile { profile_counters(modules)? } else { Vec::new() };
+    let counters: HashMap<String, u16> = profile.iter().map(|(address, name)| (name.clone(), *address)).collect();
+    let counters = options.profile.then_some(&counters);
+    let mut asm = String::new();
+    let mut source_map = Vec::new();
+    if defines_sys_init {
+        let bootstrap = codegen::format_bootstrap(options);
+        source_map.extend(std::iter::repeat_n(None, bootstrap.lines().count()));
+        asm.push_str(&bootstrap);
+    }
+    if options.stack_limit.is_some() {
+        let guard = codegen::format_stack_guard();
+        source_map.extend(std::iter::repeat_n(None, guard.lines().count()));
+        asm.push_str(&guard);
+    }
+    if options.extensions {
+        let runtime = codegen::format_extension_runtime(modules);
+        source_map.extend(std::iter::repeat_n(None, runtime.lines().count()));
+        asm.push_str(&runtime);
+    }
+    if options.optimize_size {
+        let runtime = codegen::format_runtime(options);
+        source_map.extend(std::iter::repeat_n(None, runtime.lines().count()));
+        asm.push_str(&runtime);
+    }
+    let translations: Vec<Result<(String, Vec<usize>), Error>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = modules.iter()
+            .map(|module| scope.spawn(move || {
+                let optimized;
+                let module = if options.optimize {
+                    optimized = optimize(module);
+                    &optimized
+                } else {
+                    module
+                };
+                Translator::new(&module.name, options, counters).convert_to_asm(&module.commands)
+            }))
+            .collect();
+        workers.into_iter()
+            .map(|worker| worker.join().expect("translation thread panicked"))
+            .collect()
+    });
+    // Joined in module order, so the first error reported is that of the first module.
+    for (index, translation) in translations.into_iter().enumerate() {
+        let (module_asm, lines) = translation?;
+        asm.push_str(&module_asm);
+        source_map.extend(lines.into_iter().map(|line| Some((index, line))));
+    }
+    Ok(Translation { asm, source_map, profile })
+}
+
+// One counter from `PROFILE_BASE` for each function name, in definition order.
+fn profile_counters(modules: &[VmModule]) -> Result<Vec<(u16, String)>, Error> {
+    let mut names: Vec<&String> = Vec::new();
+    for command in modules.iter().flat_map(|module| &module.commands) {
+        if let CommandType::Function(name, _) = &command.command {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    if names.len() > (STACK_ERROR_ADDRESS - PROFILE_BASE) as usize {
+        return Err(Error::TooManyProfiledFunctions { functions: names.len() });
+    }
+    Ok((PROFILE_BASE..).zip(names.into_iter().cloned()).collect())
+}
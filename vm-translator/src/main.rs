@@ -1,519 +1,416 @@
 use std::fs;
 use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-static LABEL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+use vm_translator::{analyze, count_instructions, expand_inputs, is_same_file, output_path, parse_file, read_bytecode, translate_with_options, write_bytecode, worst_case, CallGraph, Options, Translation, VmModule, DEFAULT_INLINE_LIMIT, DEFAULT_STACK_LIMIT};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 1 {
-        eprintln!("Usage: {} <file.vm>", args[0]);
+    let mut options = Options::default();
+    let mut source_map = false; // write `<output>.map` mapping each asm line to its VM line
+    let mut size_report = false; // print the instruction count of both code generation modes
+    let mut output = None; // `-o <file>`, or `-o -` for stdout
+    let mut call_graph = None; // `--call-graph <file.dot>`
+    let mut inline_limit = None; // `--inline-limit <n>`, or 0 with `--no-inline`
+    let mut analyze_only = false; // report stack usage instead of translating
+    let mut hack = false; // assemble the output into a `.hack` binary
+    let mut keep_asm = false; // with --hack, also write the `.asm` next to the `.hack`
+    let mut bytecode = false; // write the modules as one `.vmb` bytecode file instead of translating
+    let mut vm_dump = false; // write the modules back as canonical `.vm` text instead of translating
+    let mut inputs = Vec::new();
+    let mut arg_iter = args[1..].iter();
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(option_value(&mut arg_iter, arg)),
+            "--call-graph" => call_graph = Some(option_value(&mut arg_iter, arg)),
+            "--inline-limit" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(limit) => inline_limit = Some(limit),
+                Err(_) => {
+                    eprintln!("Invalid value for {}", arg);
+                    std::process::exit(1);
+                }
+            },
+            "--no-inline" => inline_limit = Some(0),
+            "--analyze" => analyze_only = true,
+            "--check-stack" => options.stack_limit = Some(options.stack_limit.unwrap_or(DEFAULT_STACK_LIMIT)),
+            "--stack-limit" => match option_value(&mut arg_iter, arg).parse() {
+                Ok(limit) => options.stack_limit = Some(limit),
+                Err(_) => {
+                    eprintln!("Invalid value for {}", arg);
+                    std::process::exit(1);
+                }
+            },
+            "--comments" => options.comments = true,
+            "--source-map" => source_map = true,
+            "--optimize-size" => options.optimize_size = true,
+            "--size-report" => size_report = true,
+            "--unchecked-compare" => options.unchecked_compare = true,
+            "--optimize" => options.optimize = true,
+            "--cache-tos" => options.cache_tos = true,
+            "--eliminate-dead-code" => options.eliminate_dead_functions = true,
+            "--tail-calls" => options.tail_calls = true,
+            "--profile" => options.profile = true,
+            "--extensions" => options.extensions = true,
+            "--hack" => hack = true,
+            "--keep-asm" => keep_asm = true,
+            "--bytecode" => bytecode = true,
+            "--vm-dump" => vm_dump = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {}", arg);
+                std::process::exit(1);
+            },
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    // Inlining comes with --optimize unless configured explicitly, or profiling, which
+    // needs every call to reach the function's counter.
+    if options.profile && inline_limit.is_some_and(|limit| limit > 0) {
+        eprintln!("--profile cannot be combined with --inline-limit");
+        std::process::exit(1);
+    }
+    let inline_by_default = options.optimize && !options.profile;
+    options.inline_limit = inline_limit.unwrap_or(if inline_by_default { DEFAULT_INLINE_LIMIT } else { 0 });
+    if inputs.is_empty() {
+        eprintln!("Usage: {} <file.vm|file.vmb|directory>... [-o <file.asm|file.hack|file.vmb|directory>|-] [--comments] [--source-map] [--optimize-size] [--size-report] [--unchecked-compare] [--optimize] [--cache-tos] [--eliminate-dead-code] [--call-graph <file.dot>] [--tail-calls] [--inline-limit <n>] [--no-inline] [--analyze] [--check-stack] [--stack-limit <n>] [--hack] [--keep-asm] [--bytecode] [--vm-dump] [--profile] [--extensions]", args[0]);
         std::process::exit(1);
     }
-    let filepath = &args[1];
+    let filepaths = match expand_inputs(&inputs) {
+        Ok(filepaths) => filepaths,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    match fs::read_to_string(filepath) {
-        Ok(file_content) => {
-            let parsed_content = parser(&file_content);
-            let asm_result = convert_to_asm(&parsed_content);
-            let output_filepath = filepath.replace(".vm", ".asm");
-            if write_file_asm(&asm_result, &output_filepath).is_err() {
-                eprintln!("Failed to write to file: {}", output_filepath);
+    let modules = match load_modules(&filepaths) {
+        Ok(modules) => modules,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    if vm_dump {
+        dump_modules(&modules, &filepaths, output.map(String::as_str));
+        return;
+    }
+    // Writes no output file, so it takes any number of inputs without -o.
+    if analyze_only {
+        let sound = print_stack_analysis(&modules);
+        std::process::exit(if sound { 0 } else { 1 });
+    }
+    let output_filepath = match output_path(&inputs, &filepaths, output.map(String::as_str), if bytecode { "vmb" } else if hack { "hack" } else { "asm" }) {
+        Ok(path) => path,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    if source_map && output_filepath.is_none() {
+        eprintln!("--source-map needs an output file, not stdout");
+        std::process::exit(1);
+    }
+    if options.profile && output_filepath.is_none() {
+        eprintln!("--profile needs an output file, not stdout");
+        std::process::exit(1);
+    }
+    if hack && keep_asm && output_filepath.is_none() {
+        eprintln!("--keep-asm needs an output file, not stdout");
+        std::process::exit(1);
+    }
+    // The kept assembly goes next to the binary, so `-o` must not end in `.asm`.
+    if hack && keep_asm && output_filepath.as_ref().is_some_and(|path| *path == path.with_extension("asm")) {
+        eprintln!("--keep-asm would write the .asm over the .hack output; give -o a .hack path");
+        std::process::exit(1);
+    }
+    if hack && source_map && !keep_asm {
+        eprintln!("--source-map maps lines of the .asm, which --hack only keeps with --keep-asm");
+        std::process::exit(1);
+    }
+
+    if bytecode {
+        let content = write_bytecode(&modules);
+        let written = match &output_filepath {
+            Some(output_filepath) => fs::write(output_filepath, content),
+            None => std::io::stdout().write_all(&content),
+        };
+        if written.is_err() {
+            eprintln!("Failed to write the bytecode");
+            std::process::exit(1);
+        }
+        if let Some(output_filepath) = output_filepath {
+            println!("Bytecode written: {}", output_filepath.display());
+        }
+        return;
+    }
+    let translation = match translate_with_options(&modules, &options) {
+        Ok(translation) => translation,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // The assembler only fails on mnemonics the translator never emits.
+    let output_content = if hack {
+        match assembler::assemble(&translation.asm) {
+            Ok(binary) => binary,
+            Err(message) => {
+                eprintln!("Failed to assemble the translation: {}", message);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        translation.asm.clone()
+    };
+    if let Some(call_graph_filepath) = call_graph {
+        let dot = CallGraph::new(&modules).to_dot("Sys.init");
+        if write_file_asm(&dot, Path::new(call_graph_filepath)).is_err() {
+            eprintln!("Failed to write to file: {}", call_graph_filepath);
+            std::process::exit(1);
+        }
+    }
+
+    // Reports go to stdout, unless the assembly itself does.
+    let mut report: Box<dyn Write> = match &output_filepath {
+        Some(output_filepath) => {
+            if write_file_asm(&output_content, output_filepath).is_err() {
+                eprintln!("Failed to write to file: {}", output_filepath.display());
                 std::process::exit(1);
             }
-            println!("Translation completed successfully: {}", output_filepath);
+            let asm_filepath = if hack { output_filepath.with_extension("asm") } else { output_filepath.clone() };
+            if hack && keep_asm && write_file_asm(&translation.asm, &asm_filepath).is_err() {
+                eprintln!("Failed to write to file: {}", asm_filepath.display());
+                std::process::exit(1);
+            }
+            if source_map {
+                let mut map_filepath = asm_filepath.into_os_string();
+                map_filepath.push(".map");
+                let map_filepath = PathBuf::from(map_filepath);
+                if write_file_asm(&translation.format_source_map(&modules), &map_filepath).is_err() {
+                    eprintln!("Failed to write to file: {}", map_filepath.display());
+                    std::process::exit(1);
+                }
+            }
+            if options.profile {
+                let mut manifest_filepath = output_filepath.clone().into_os_string();
+                manifest_filepath.push(".profile");
+                let manifest_filepath = PathBuf::from(manifest_filepath);
+                if write_file_asm(&format_profile_manifest(&translation.profile), &manifest_filepath).is_err() {
+                    eprintln!("Failed to write to file: {}", manifest_filepath.display());
+                    std::process::exit(1);
+                }
+            }
+            println!("Translation completed successfully: {}", output_filepath.display());
+            Box::new(std::io::stdout())
         },
-        Err(e) => {
-            eprintln!("Failed to read the file '{}': {}", filepath, e);
+        None => {
+            if std::io::stdout().write_all(output_content.as_bytes()).is_err() {
+                eprintln!("Failed to write to stdout");
+                std::process::exit(1);
+            }
+            Box::new(std::io::stderr())
+        },
+    };
+    if options.eliminate_dead_functions {
+        print_dead_function_report(&mut report, &modules);
+    }
+    if size_report {
+        print_size_report(&mut report, &modules, &options);
+    }
+    if options.optimize {
+        print_optimization_report(&mut report, &modules, &options);
+    }
+}
+
+// The value following an option such as `-o`.
+fn option_value<'a>(arg_iter: &mut impl Iterator<Item = &'a String>, option: &str) -> &'a String {
+    match arg_iter.next() {
+        Some(value) => value,
+        None => {
+            eprintln!("Missing value for {}", option);
             std::process::exit(1);
         }
     }
 }
 
-fn parser(file_content: &str) -> Vec<CommandType> {
-    file_content.lines()
-        .filter_map(parse_line)
-        .collect()
+// Reads and parses every file on its own thread, keeping the modules in file order.
+// Bytecode files (`.vmb`) hold any number of modules, named inside; a `.vm` file is
+// one module named after the file.
+fn load_modules(filepaths: &[PathBuf]) -> Result<Vec<VmModule>, String> {
+    let loaded: Vec<Result<Vec<VmModule>, String>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = filepaths.iter()
+            .map(|filepath| scope.spawn(move || load_file(filepath)))
+            .collect();
+        workers.into_iter()
+            .map(|worker| worker.join().expect("loading thread panicked"))
+            .collect()
+    });
+    let mut modules = Vec::new();
+    for file_modules in loaded {
+        modules.extend(file_modules?);
+    }
+    Ok(modules)
 }
 
+fn load_file(filepath: &Path) -> Result<Vec<VmModule>, String> {
+    let read_error = |e: std::io::Error| format!("Failed to read the file '{}': {}", filepath.display(), e);
+    if filepath.extension().is_some_and(|extension| extension == "vmb") {
+        let file_content = fs::read(filepath).map_err(read_error)?;
+        return read_bytecode(&filepath.display().to_string(), &file_content).map_err(|e| e.to_string());
+    }
+    let file_content = fs::read_to_string(filepath).map_err(read_error)?;
+    parse_file(filepath, &file_content).map(|module| vec![module]).map_err(|e| e.to_string())
+}
 
-fn convert_to_asm(parsed_content: &[CommandType]) -> String {
-    let mut asm_result = String::new();
-    for command in parsed_content {
-        match command {
-            CommandType::Push(segment, index) => {
-                let asm_code = match segment.as_str() {
-                    "constant" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n"
-                    ),
-                    "local" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @LCL\n\
-                        A=M+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n"
-                    ),
-                    "argument" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @ARG\n\
-                        A=M+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n"
-                    ),
-                    "this" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @THIS\n\
-                        A=M+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n"
-                    ),
-                    "that" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @THAT\n\
-                        A=M+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n"
-                    ),
-                    "temp" => format!(
-                        "@{}\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n",
-                        5 + index
-                    ),
-                    "pointer" => format!(
-                        "@{}\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n",
-                        if *index == 0 { "THIS" } else { "THAT" }
-                    ),
-                    "static" => format!(
-                        "@{}.{}\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1\n",
-                        "Foo", index
-                    ),
-                    _ => panic!("Unsupported push segment: {}", segment),
-                };
-                asm_result.push_str(&asm_code);
-            },
-            CommandType::Pop(segment, index) => {
-                let asm_code = match segment.as_str() {
-                    "local" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @LCL\n\
-                        D=M+D\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D\n"
-                    ),
-                    "argument" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @ARG\n\
-                        D=M+D\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D\n"
-                    ),
-                    "this" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @THIS\n\
-                        D=M+D\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D\n"
-                    ),
-                    "that" => format!(
-                        "@{index}\n\
-                        D=A\n\
-                        @THAT\n\
-                        D=M+D\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D\n"
-                    ),
-                    "temp" => format!(
-                        "@SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @{}\n\
-                        M=D\n",
-                        5 + index
-                    ),
-                    "pointer" => format!(
-                        "@SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @{}\n\
-                        M=D\n",
-                        if *index == 0 { "THIS" } else { "THAT" }
-                    ),
-                    "static" => format!(
-                        "@SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @{}.{}\n\
-                        M=D\n",
-                        "Foo", index
-                    ),
-                    _ => panic!("Unsupported pop segment: {}", segment),
-                };
-                asm_result.push_str(&asm_code);
-            },
-            CommandType::Arithmetic(operation) => {
-                asm_result.push_str(&format_arithmetic(operation));
-            },
-            CommandType::Label(label) => {
-                asm_result.push_str(&format!("({})\n", label));
-            },
-            CommandType::Goto(label) => {
-                asm_result.push_str(&format!(
-                    "@{label}\n\
-                    0;JMP\n"
-                ));
-            },
-            CommandType::If(label) => {
-                asm_result.push_str(&format!(
-                    "@SP\n\
-                    AM=M-1\n\
-                    D=M\n\
-                    @{label}\n\
-                    D;JNE\n"
-                ));
-            },
-            CommandType::Function(name, num_locals) => {
-                asm_result.push_str(&format!("({name})\n"));
-                for _ in 0..*num_locals {
-                    asm_result.push_str(
-                        "@SP\n\
-                        A=M\n\
-                        M=0\n\
-                        @SP\n\
-                        M=M+1\n"
-                    );
-                }
-            },
-            CommandType::Return => {
-                asm_result.push_str(
-                    "@LCL\n\
-                    D=M\n\
-                    @R14\n\
-                    M=D\n\
-                    @5\n\
-                    A=D-A\n\
-                    D=M\n\
-                    @R15\n\
-                    M=D\n\
-                    @SP\n\
-                    A=M-1\n\
-                    D=M\n\
-                    @ARG\n\
-                    A=M\n\
-                    M=D\n\
-                    D=A+1\n\
-                    @SP\n\
-                    M=D\n\
-                    @R14\n\
-                    AM=M-1\n\
-                    D=M\n\
-                    @THAT\n\
-                    M=D\n\
-                    @R14\n\
-                    AM=M-1\n\
-                    D=M\n\
-                    @THIS\n\
-                    M=D\n\
-                    @R14\n\
-                    AM=M-1\n\
-                    D=M\n\
-                    @ARG\n\
-                    M=D\n\
-                    @R14\n\
-                    AM=M-1\n\
-                    D=M\n\
-                    @LCL\n\
-                    M=D\n\
-                    @R15\n\
-                    A=M\n\
-                    0;JMP\n"
-                );
-            },
-            CommandType::Call(name, num_args) => {
-                let return_label = unique_label("RETURN_LABEL"); // Generate a unique return label
-                asm_result.push_str(&format!(
-                    "@{return_label}\n\
-                    D=A\n\
-                    @SP\n\
-                    A=M\n\
-                    M=D\n\
-                    @SP\n\
-                    M=M+1\n\
-                    @LCL\n\
-                    D=M\n\
-                    @SP\n\
-                    A=M\n\
-                    M=D\n\
-                    @SP\n\
-                    M=M+1\n\
-                    @ARG\n\
-                    D=M\n\
-                    @SP\n\
-                    A=M\n\
-                    M=D\n\
-                    @SP\n\
-                    M=M+1\n\
-                    @THIS\n\
-                    D=M\n\
-                    @SP\n\
-                    A=M\n\
-                    M=D\n\
-                    @SP\n\
-                    M=M+1\n\
-                    @THAT\n\
-                    D=M\n\
-                    @SP\n\
-                    A=M\n\
-                    M=D\n\
-                    @SP\n\
-                    M=M+1\n\
-                    @SP\n\
-                    D=M\n\
-                    @5\n\
-                    D=D-A\n\
-                    @{num_args}\n\
-                    D=D-A\n\
-                    @ARG\n\
-                    M=D\n\
-                    @SP\n\
-                    D=M\n\
-                    @LCL\n\
-                    M=D\n\
-                    @{name}\n\
-                    0;JMP\n\
-                    ({return_label})\n"
-                ));
-            },
+// Writes every module as canonical text, one command per line, to `Name.vm` in the
+// requested directory (by default the one holding the first input), or all of them to
+// stdout with `-`, each after a `// Name.vm` header. Never overwrites an input.
+fn dump_modules(modules: &[VmModule], filepaths: &[PathBuf], requested: Option<&str>) {
+    let directory = match requested {
+        Some("-") => None,
+        Some(directory) => Some(PathBuf::from(directory)),
+        None => Some(filepaths[0].parent().map(Path::to_path_buf).unwrap_or_default()),
+    };
+    for module in modules {
+        let text: String = module.commands.iter().map(|vm_command| format!("{}\n", vm_command.command)).collect();
+        let Some(directory) = &directory else {
+            print!("// {}.vm\n{}", module.name, text);
+            continue;
+        };
+        let filepath = directory.join(format!("{}.vm", module.name));
+        if filepaths.iter().any(|input| is_same_file(input, &filepath)) {
+            eprintln!("Refusing to overwrite the source file: {}", filepath.display());
+            std::process::exit(1);
         }
+        if write_file_asm(&text, &filepath).is_err() {
+            eprintln!("Failed to write to file: {}", filepath.display());
+            std::process::exit(1);
+        }
+        println!("Module written: {}", filepath.display());
     }
-    asm_result
 }
 
-fn parse_line(line: &str) -> Option<CommandType> {
-    let line = line.trim();
-    if line.is_empty() || line.starts_with("//") {
-        None
+// One line per profiled function: `<counter address> <function name>`.
+fn format_profile_manifest(profile: &[(u16, String)]) -> String {
+    profile.iter()
+        .map(|(address, name)| format!("{} {}\n", address, name))
+        .collect()
+}
+
+// Prints per-function stack usage, the worst case along the deepest call chain and any
+// inconsistency found. Returns false if there were inconsistencies or the stack can
+// grow into the heap.
+fn print_stack_analysis(modules: &[VmModule]) -> bool {
+    let functions = analyze(modules);
+    println!("{:<32} {:>6} {:>9} {:>10}", "Function", "Locals", "Max depth", "Worst case");
+    for function in &functions {
+        println!(
+            "{:<32} {:>6} {:>9} {:>10}",
+            function.name, function.num_locals, function.max_depth, worst_case(&functions, &function.name).words
+        );
+    }
+    let called: Vec<&str> = functions.iter()
+        .flat_map(|function| function.calls.iter().map(|(callee, _)| callee.as_str()))
+        .collect();
+    let roots: Vec<&str> = if functions.iter().any(|function| function.name == "Sys.init") {
+        vec!["Sys.init"]
     } else {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            return None;
+        functions.iter()
+            .map(|function| function.name.as_str())
+            .filter(|name| !called.contains(name))
+            .collect()
+    };
+    let mut sound = true;
+    for root in roots {
+        let worst_case = worst_case(&functions, root);
+        // The bootstrap sets SP to 256 and calls Sys.init with a 5 word frame.
+        let highest_sp = 256 + 5 + worst_case.words;
+        println!();
+        println!("Worst case from {}: {} words, SP up to {}", root, worst_case.words, highest_sp);
+        println!("  {}", worst_case.chain.join(" -> "));
+        for (caller, callee) in &worst_case.recursive_calls {
+            println!("  recursive call not counted: {} -> {}", caller, callee);
+        }
+        for function in &worst_case.undefined {
+            println!("  undefined function counted as 0: {}", function);
         }
-        match parts[0] {
-            "push" if parts.len() == 3 => parts[2].parse::<i16>().ok().map(|index| CommandType::Push(parts[1].to_string(), index)),
-            "pop" if parts.len() == 3 => parts[2].parse::<i16>().ok().map(|index| CommandType::Pop(parts[1].to_string(), index)),
-            "label" if parts.len() == 2 => Some(CommandType::Label(parts[1].to_string())),
-            "goto" if parts.len() == 2 => Some(CommandType::Goto(parts[1].to_string())),
-            "if-goto" if parts.len() == 2 => Some(CommandType::If(parts[1].to_string())),
-            "function" if parts.len() == 3 => parts[2].parse::<usize>().ok().map(|num_args| CommandType::Function(parts[1].to_string(), num_args)),
-            "call" if parts.len() == 3 => parts[2].parse::<usize>().ok().map(|num_args| CommandType::Call(parts[1].to_string(), num_args)),
-            "return" if parts.len() == 1 => Some(CommandType::Return),
-            "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" if parts.len() == 1 => Some(CommandType::Arithmetic(parts[0].to_string())),
-            _ => None,
+        if highest_sp > 2047 {
+            println!("  the stack can grow past 2047 into the heap");
+            sound = false;
         }
     }
+    let issues: Vec<&String> = functions.iter().flat_map(|function| &function.issues).collect();
+    if !issues.is_empty() {
+        println!();
+        for issue in issues {
+            println!("{}", issue);
+        }
+        sound = false;
+    }
+    sound
 }
 
+fn print_dead_function_report(out: &mut dyn Write, modules: &[VmModule]) {
+    let call_graph = CallGraph::new(modules);
+    if !call_graph.calls.contains_key("Sys.init") {
+        let _ = writeln!(out, "No Sys.init, so no functions were removed");
+        return;
+    }
+    let reachable = call_graph.reachable_from("Sys.init");
+    let removed: Vec<&str> = call_graph.calls.keys()
+        .filter(|function| !reachable.contains(*function))
+        .map(String::as_str)
+        .collect();
+    if removed.is_empty() {
+        let _ = writeln!(out, "No unreachable functions");
+    } else {
+        let _ = writeln!(out, "Removed {} unreachable functions: {}", removed.len(), removed.join(", "));
+    }
+}
 
-fn format_arithmetic(operation: &str) -> String {
-    match operation {
-        "add" => {
-            String::from(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                M=D+M\n"
-            )
-        },
-        "sub" => {
-            String::from(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                M=M-D\n"
-            )
-        },
-        "neg" => {
-            String::from(
-                "@SP\n\
-                A=M-1\n\
-                M=-M\n"
-            )
-        },
-        "eq" => {
-            let label = unique_label("EQ");
-            format!(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                D=M-D\n\
-                M=-1\n\
-                @{}\n\
-                D;JEQ\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                ({})
-
-                ", label, label
-            )
-        },
-        "gt" => {
-            let label = unique_label("GT");
-            format!(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                D=M-D\n\
-                M=-1\n\
-                @{}\n\
-                D;JGT\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                ({})
-
-                ", label, label
-            )
-        },
-        "lt" => {
-            let label = unique_label("LT");
-            format!(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                D=M-D\n\
-                M=-1\n\
-                @{}\n\
-                D;JLT\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                ({})
+fn print_size_report(out: &mut dyn Write, modules: &[VmModule], options: &Options) {
+    let default_options = Options { optimize_size: false, ..options.clone() };
+    let size_options = Options { optimize_size: true, ..options.clone() };
+    // Both translations already succeeded once with the same modules.
+    let default_size = count_instructions(&translate_with_options(modules, &default_options).unwrap().asm);
+    let optimized_size = count_instructions(&translate_with_options(modules, &size_options).unwrap().asm);
+    let saved = default_size as isize - optimized_size as isize;
+    let _ = writeln!(out, "Code size (instructions):");
+    let _ = writeln!(out, "  default:        {}", default_size);
+    let _ = writeln!(
+        out,
+        "  size-optimized: {} ({:+}, {:.1}% saved)",
+        optimized_size,
+        -saved,
+        if default_size == 0 { 0.0 } else { saved as f64 * 100.0 / default_size as f64 }
+    );
+}
 
-                ", label, label
-            )
-        },
-        "and" => {
-            String::from(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                M=D&M\n"
-            )
-        },
-        "or" => {
-            String::from(
-                "@SP\n\
-                AM=M-1\n\
-                D=M\n\
-                A=A-1\n\
-                M=D|M\n"
-            )
-        },
-        "not" => {
-            String::from(
-                "@SP\n\
-                A=M-1\n\
-                M=!M\n"
-            )
-        },
-        _ => panic!("Unsupported arithmetic operation: {}", operation),
+fn print_optimization_report(out: &mut dyn Write, modules: &[VmModule], options: &Options) {
+    let baseline_options = Options { optimize: false, inline_limit: 0, ..options.clone() };
+    let before = instructions_per_module(&translate_with_options(modules, &baseline_options).unwrap(), modules.len());
+    let after = instructions_per_module(&translate_with_options(modules, options).unwrap(), modules.len());
+    for ((module, before), after) in modules.iter().zip(before).zip(after) {
+        let _ = writeln!(
+            out,
+            "{}.vm: {} -> {} instructions ({:+})",
+            module.name, before, after, after as isize - before as isize
+        );
     }
 }
 
-fn unique_label(base: &str) -> String {
-    let count = LABEL_COUNTER.fetch_add(1, Ordering::SeqCst);
-    format!("{}{}", base, count)
+// Instructions attributed to each module by the source map; bootstrap and runtime code
+// are not counted.
+fn instructions_per_module(translation: &Translation, module_count: usize) -> Vec<usize> {
+    let mut counts = vec![0; module_count];
+    for (line, location) in translation.asm.lines().zip(&translation.source_map) {
+        if let Some((module, _)) = location {
+            counts[*module] += count_instructions(line);
+        }
+    }
+    counts
 }
 
-fn write_file_asm(file_content: &str, filename: &str) -> Result<(), std::io::Error> {
+fn write_file_asm(file_content: &str, filename: &Path) -> Result<(), std::io::Error> {
     fs::write(filename, file_content)
 }
-
-enum CommandType {
-    Arithmetic(String),
-    Push(String, i16),
-    Pop(String, i16),
-    Label(String),
-    Goto(String),
-    If(String),
-    Function(String, usize),
-    Return,
-    Call(String, usize),
-}
@@ -0,0 +1,83 @@
+use crate::parser::{CommandType, VmCommand, VmModule};
+
+/// Folds arithmetic on constants and drops operations that cannot change the value on
+/// top of the stack (`x + 0`, `x - 0`, `x | 0`, `x & -1`, `neg; neg`, `not; not`).
+/// Only straight runs of pushes and arithmetic are rewritten; any other command ends a run.
+pub fn optimize(module: &VmModule) -> VmModule {
+    let mut commands = Vec::new();
+    // Constants pushed by the current run but not emitted yet, with the line that pushed each.
+    let mut pending: Vec<(i16, usize)> = Vec::new();
+    for vm_command in &module.commands {
+        match &vm_command.command {
+            CommandType::Push(segment, value) if segment == "constant" && *value >= 0 => {
+                pending.push((*value, vm_command.line));
+            },
+            CommandType::Arithmetic(operation) if operation == "neg" || operation == "not" => {
+                if let Some((value, _)) = pending.last_mut() {
+                    *value = if operation == "neg" { value.wrapping_neg() } else { !*value };
+                } else if commands.last().is_some_and(|last: &VmCommand| last.command == vm_command.command) {
+                    commands.pop();
+                } else {
+                    commands.push(vm_command.clone());
+                }
+            },
+            CommandType::Arithmetic(operation) if pending.len() >= 2 => {
+                let (y, _) = pending.pop().unwrap();
+                let (x, line) = pending.pop().unwrap();
+                match fold(operation, x, y) {
+                    Some(value) => pending.push((value, line)),
+                    None => {
+                        pending.push((x, line));
+                        pending.push((y, line));
+                        flush(&mut pending, &mut commands);
+                        commands.push(vm_command.clone());
+                    }
+                }
+            },
+            CommandType::Arithmetic(operation) if pending.len() == 1 && is_identity(operation, pending[0].0) => {
+                pending.clear();
+            },
+            _ => {
+                flush(&mut pending, &mut commands);
+                commands.push(vm_command.clone());
+            },
+        }
+    }
+    flush(&mut pending, &mut commands);
+    VmModule { name: module.name.clone(), commands }
+}
+
+// Hack arithmetic wraps around, and comparisons produce -1 (true) or 0 (false).
+fn fold(operation: &str, x: i16, y: i16) -> Option<i16> {
+    let value = match operation {
+        "add" => x.wrapping_add(y),
+        "sub" => x.wrapping_sub(y),
+        "and" => x & y,
+        "or" => x | y,
+        "eq" => -i16::from(x == y),
+        "gt" => -i16::from(x > y),
+        "lt" => -i16::from(x < y),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn is_identity(operation: &str, y: i16) -> bool {
+    matches!((operation, y), ("add" | "sub" | "or", 0) | ("and", -1))
+}
+
+// Emits pending constants in the canonical form: `push constant n`, or `push constant n;
+// neg` for negative values, since the VM only accepts non-negative constants.
+fn flush(pending: &mut Vec<(i16, usize)>, commands: &mut Vec<VmCommand>) {
+    for (value, line) in pending.drain(..) {
+        let push = |value: i16| VmCommand { line, command: CommandType::Push("constant".to_string(), value) };
+        let arithmetic = |operation: &str| VmCommand { line, command: CommandType::Arithmetic(operation.to_string()) };
+        if value >= 0 {
+            commands.push(push(value));
+        } else if value == i16::MIN {
+            commands.extend([push(i16::MAX), arithmetic("neg"), push(1), arithmetic("sub")]);
+        } else {
+            commands.extend([push(-value), arithmetic("neg")]);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::Error;
+
+/// A parsed `.vm` file. `name` is the file name without extension (`Main` for
+/// `Main.vm`) and prefixes the module's static symbols.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmModule {
+    pub name: String,
+    pub commands: Vec<VmCommand>,
+}
+
+impl VmModule {
+    /// Builds a module from commands that have no source file, numbering them from 1.
+    pub fn from_commands(name: &str, commands: Vec<CommandType>) -> Self {
+        let commands = commands.into_iter()
+            .enumerate()
+            .map(|(index, command)| VmCommand { line: index + 1, command })
+            .collect();
+        VmModule { name: name.to_string(), commands }
+    }
+}
+
+/// Parses the text of a `.vm` file. Comments (`// ...`) may appear on their own
+/// line or after a command; anything else that is not a command is an error, which
+/// names the module as its file.
+pub fn parse(name: &str, source: &str) -> Result<VmModule, Error> {
+    parse_source(name, name, source)
+}
+
+/// Parses the text read from `filepath`, naming the module after the file without its
+/// extension. Errors give the path as it was passed.
+pub fn parse_file(filepath: &Path, source: &str) -> Result<VmModule, Error> {
+    let name = filepath.file_stem().unwrap_or_default().to_string_lossy();
+    parse_source(&name, &filepath.display().to_string(), source)
+}
+
+fn parse_source(name: &str, file: &str, source: &str) -> Result<VmModule, Error> {
+    let mut commands = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let text = line.split("//").next().unwrap_or_default().trim();
+        if text.is_empty() {
+            continue;
+        }
+        match parse_line(text) {
+            Some(command) => commands.push(VmCommand { line: index + 1, command }),
+            None => return Err(Error::Syntax { file: file.to_string(), line: index + 1, text: text.to_string() }),
+        }
+    }
+    Ok(VmModule { name: name.to_string(), commands })
+}
+
+// Expects a line with comments and surrounding whitespace already stripped.
+fn parse_line(line: &str) -> Option<CommandType> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.is_empty() {
+        return None;
+    }
+    match parts[0] {
+        "push" if parts.len() == 3 => parts[2].parse::<i16>().ok().map(|index| CommandType::Push(parts[1].to_string(), index)),
+        "pop" if parts.len() == 3 => parts[2].parse::<i16>().ok().map(|index| CommandType::Pop(parts[1].to_string(), index)),
+        "label" if parts.len() == 2 && is_label(parts[1]) => Some(CommandType::Label(parts[1].to_string())),
+        "goto" if parts.len() == 2 && is_label(parts[1]) => Some(CommandType::Goto(parts[1].to_string())),
+        "if-goto" if parts.len() == 2 && is_label(parts[1]) => Some(CommandType::If(parts[1].to_string())),
+        "function" if parts.len() == 3 => parts[2].parse::<usize>().ok().map(|num_args| CommandType::Function(parts[1].to_string(), num_args)),
+        "call" if parts.len() == 3 => parts[2].parse::<usize>().ok().map(|num_args| CommandType::Call(parts[1].to_string(), num_args)),
+        "return" if parts.len() == 1 => Some(CommandType::Return),
+        "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" if parts.len() == 1 => Some(CommandType::Arithmetic(parts[0].to_string())),
+        // Extensions, rejected during translation unless enabled.
+        "mult" | "div" | "mod" | "shl" | "shr" if parts.len() == 1 => Some(CommandType::Arithmetic(parts[0].to_string())),
+        _ => None,
+    }
+}
+
+// VM labels may not contain `$`, which separates the function from the label in the
+// generated assembly and marks the labels the translator makes up.
+pub(crate) fn is_label(name: &str) -> bool {
+    !name.contains('$')
+}
+
+/// A parsed command together with the (1-based) source line it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmCommand {
+    pub line: usize,
+    pub command: CommandType,
+}
+
+/// The VM command set of projects 7 and 8. Segments and operations are kept as the
+/// strings found in the source; invalid ones are reported during translation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandType {
+    Arithmetic(String),
+    Push(String, i16),
+    Pop(String, i16),
+    Label(String),
+    Goto(String),
+    If(String),
+    Function(String, usize),
+    Return,
+    Call(String, usize),
+}
+
+impl fmt::Display for CommandType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandType::Arithmetic(operation) => write!(f, "{}", operation),
+            CommandType::Push(segment, index) => write!(f, "push {} {}", segment, index),
+            CommandType::Pop(segment, index) => write!(f, "pop {} {}", segment, index),
+            CommandType::Label(label) => write!(f, "label {}", label),
+            CommandType::Goto(label) => write!(f, "goto {}", label),
+            CommandType::If(label) => write!(f, "if-goto {}", label),
+            CommandType::Function(name, num_locals) => write!(f, "function {} {}", name, num_locals),
+            CommandType::Return => write!(f, "return"),
+            CommandType::Call(name, num_args) => write!(f, "call {} {}", name, num_args),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files named on the command line, with directories standing for the `.vm` files
+/// directly inside them, in name order.
+pub fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut filepaths = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let entries = fs::read_dir(input)
+                .map_err(|e| format!("Failed to read the directory '{}': {}", input.display(), e))?;
+            let mut vm_files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "vm"))
+                .collect();
+            vm_files.sort();
+            filepaths.extend(vm_files);
+        } else {
+            filepaths.push(input.clone());
+        }
+    }
+    Ok(filepaths)
+}
+
+/// Where the output of `inputs`, expanded to `filepaths`, goes: the `requested` path,
+/// `None` for stdout (`-`), or by default the input path with its extension replaced by
+/// `extension` (`Dir/Dir.asm` for a directory). Never one of the input files.
+pub fn output_path(
+    inputs: &[PathBuf],
+    filepaths: &[PathBuf],
+    requested: Option<&str>,
+    extension: &str,
+) -> Result<Option<PathBuf>, String> {
+    let output = match (requested, inputs) {
+        (Some("-"), _) => return Ok(None),
+        (Some(path), _) => PathBuf::from(path),
+        (None, [directory]) if directory.is_dir() => {
+            // Not `with_extension`, which would replace the part after a dot in the name.
+            let name = fs::canonicalize(directory)
+                .ok()
+                .and_then(|directory| directory.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "out".into());
+            directory.join(format!("{}.{}", name, extension))
+        },
+        (None, [input]) => input.with_extension(extension),
+        (None, _) => return Err(String::from("Several inputs need an output file, given with -o")),
+    };
+    if let Some(input) = filepaths.iter().find(|input| is_same_file(input, &output)) {
+        return Err(format!("Refusing to overwrite the source file: {}", input.display()));
+    }
+    Ok(Some(output))
+}
+
+/// Whether both paths name the same file, comparing them as given when either does not
+/// exist yet.
+pub fn is_same_file(first: &Path, second: &Path) -> bool {
+    match (fs::canonicalize(first), fs::canonicalize(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => first == second,
+    }
+}
@@ -0,0 +1,131 @@
+use vm_translator::{analyze, parse, worst_case, FunctionAnalysis};
+
+fn functions(source: &str) -> Vec<FunctionAnalysis> {
+    analyze(&[parse("Main", source).unwrap()])
+}
+
+fn issues(source: &str) -> Vec<String> {
+    functions(source).into_iter().flat_map(|function| function.issues).collect()
+}
+
+#[test]
+fn worst_case_follows_the_deepest_call_chain() {
+    let source = "\
+        function Sys.init 0\n\
+        push constant 1\n\
+        push constant 2\n\
+        call Main.a 2\n\
+        pop temp 0\n\
+        call Main.b 0\n\
+        pop temp 0\n\
+        label END\n\
+        goto END\n\
+        function Main.a 1\n\
+        push argument 0\n\
+        push argument 1\n\
+        add\n\
+        push constant 4\n\
+        call Main.b 2\n\
+        return\n\
+        function Main.b 2\n\
+        push constant 1\n\
+        push constant 2\n\
+        push constant 3\n\
+        add\n\
+        add\n\
+        return\n";
+    let functions = functions(source);
+    let depths: Vec<(&str, usize, usize)> = functions.iter()
+        .map(|function| (function.name.as_str(), function.num_locals, function.max_depth))
+        .collect();
+    assert_eq!(depths, [("Sys.init", 0, 2), ("Main.a", 1, 2), ("Main.b", 2, 3)]);
+    assert_eq!(functions[0].calls, [("Main.a".to_string(), 2), ("Main.b".to_string(), 0)]);
+    assert!(functions.iter().all(|function| function.issues.is_empty()));
+
+    // Main.b: 2 locals + 3 operands. Main.a: 1 local + 2 operands + a frame for Main.b.
+    // Sys.init: 2 operands + a frame for Main.a.
+    assert_eq!(worst_case(&functions, "Main.b").words, 5);
+    assert_eq!(worst_case(&functions, "Main.a").words, 1 + 2 + 5 + 5);
+    let worst_case = worst_case(&functions, "Sys.init");
+    assert_eq!(worst_case.words, 2 + 5 + 13);
+    assert_eq!(worst_case.chain, ["Sys.init", "Main.a", "Main.b"]);
+    assert!(worst_case.recursive_calls.is_empty());
+    assert!(worst_case.undefined.is_empty());
+}
+
+#[test]
+fn recursive_calls_are_left_out_of_the_worst_case() {
+    let source = "\
+        function Main.even 0\n\
+        push argument 0\n\
+        push constant 1\n\
+        sub\n\
+        call Main.odd 1\n\
+        return\n\
+        function Main.odd 0\n\
+        push argument 0\n\
+        call Main.even 1\n\
+        push argument 0\n\
+        call Main.odd 1\n\
+        add\n\
+        return\n";
+    let functions = functions(source);
+    let worst_case = worst_case(&functions, "Main.even");
+    // Main.even: 2 operands, or 1 below a frame for Main.odd, which has 2 operands.
+    assert_eq!(worst_case.words, 1 + 5 + 2);
+    assert_eq!(worst_case.chain, ["Main.even", "Main.odd"]);
+    let recursive_calls: Vec<(&str, &str)> = worst_case.recursive_calls.iter()
+        .map(|(caller, callee)| (caller.as_str(), callee.as_str()))
+        .collect();
+    assert_eq!(recursive_calls, [("Main.odd", "Main.even"), ("Main.odd", "Main.odd")]);
+}
+
+#[test]
+fn undefined_functions_count_as_using_no_stack() {
+    let functions = functions("function Main.main 0\ncall Math.multiply 0\nreturn\n");
+    let worst_case = worst_case(&functions, "Main.main");
+    assert_eq!(worst_case.words, 5);
+    assert_eq!(worst_case.chain, ["Main.main", "Math.multiply"]);
+    assert!(worst_case.undefined.contains("Math.multiply"));
+}
+
+#[test]
+fn paths_reaching_a_label_with_different_heights_are_reported() {
+    let source = "\
+        function Main.main 0\n\
+        push constant 1\n\
+        if-goto SKIP\n\
+        push constant 2\n\
+        label SKIP\n\
+        push constant 0\n\
+        return\n";
+    let issues = issues(source);
+    assert_eq!(issues.len(), 1, "{:?}", issues);
+    assert!(issues[0].starts_with("Main.vm:5: `label SKIP` reached with stack height"), "{}", issues[0]);
+}
+
+#[test]
+fn returns_with_different_heights_are_reported() {
+    let source = "\
+        function Main.main 0\n\
+        push constant 1\n\
+        if-goto TWO\n\
+        push constant 1\n\
+        return\n\
+        label TWO\n\
+        push constant 1\n\
+        push constant 2\n\
+        return\n";
+    let issues = issues(source);
+    assert_eq!(issues.len(), 1, "{:?}", issues);
+    assert!(issues[0].contains("`return` with stack height"), "{}", issues[0]);
+}
+
+#[test]
+fn popping_from_an_empty_stack_is_reported() {
+    assert_eq!(
+        issues("function Main.main 0\npop temp 0\npush constant 0\nreturn\n"),
+        ["Main.vm:2: `pop temp 0` pops from an empty stack"]
+    );
+    assert_eq!(issues("function Main.main 0\nadd\nreturn\n"), ["Main.vm:2: `add` pops from an empty stack"]);
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// A fresh directory holding the given `.vm` files.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("vm-translator-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    for (file, source) in files {
+        fs::write(directory.join(file), source).unwrap();
+    }
+    directory
+}
+
+fn translator(args: &[&str], directory: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vm_translator")).args(args).current_dir(directory).output().unwrap()
+}
+
+const SYS: &str = "function Sys.init 0\ncall Main.main 0\nlabel END\ngoto END\n";
+const MAIN: &str = "function Main.main 0\npush constant 1\nreturn\n";
+
+#[test]
+fn analyze_takes_several_inputs_without_an_output_file() {
+    let directory = project("analyze", &[("Main.vm", MAIN), ("Sys.vm", SYS)]);
+    let output = translator(&["--analyze", "Main.vm", "Sys.vm"], &directory);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Worst case from Sys.init"));
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+}
+
+#[test]
+fn kept_assembly_may_not_replace_the_binary() {
+    let directory = project("keep-asm", &[("Main.vm", MAIN), ("Sys.vm", SYS)]);
+    let output = translator(&["--hack", "--keep-asm", "-o", "Foo.asm", "Main.vm", "Sys.vm"], &directory);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--keep-asm"));
+    assert!(!directory.join("Foo.asm").exists());
+
+    let output = translator(&["--hack", "--keep-asm", "-o", "Foo.hack", "Main.vm", "Sys.vm"], &directory);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(fs::read_to_string(directory.join("Foo.hack")).unwrap().starts_with("0000000100000000\n"));
+    assert!(fs::read_to_string(directory.join("Foo.asm")).unwrap().starts_with("@256\n"));
+}
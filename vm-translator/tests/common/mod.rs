@@ -0,0 +1,52 @@
+//! Runs translated VM programs on the emulator.
+
+// Each test crate uses part of this.
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, HashMap};
+
+use cpu_emulator::{load_hack, Computer};
+use vm_translator::{parse, translate_with_options, Options, VmModule};
+
+// Longer than any of the test programs runs.
+const MAX_CYCLES: u64 = 1_000_000;
+
+/// A program run to its halt loop, with the assembler's symbols to find its statics.
+pub struct Run {
+    pub computer: Computer,
+    symbols: HashMap<String, u16>,
+}
+
+impl Run {
+    /// The static variables, `Module.index`, and their values. Compared by name, as
+    /// the assembler places them in order of first use, which optimizations can change.
+    pub fn statics(&self) -> BTreeMap<String, i16> {
+        self.symbols.iter()
+            .filter(|(name, _)| name.rsplit_once('.').is_some_and(|(_, index)| index.parse::<u16>().is_ok()))
+            .map(|(name, address)| (name.clone(), self.computer.ram[*address as usize] as i16))
+            .collect()
+    }
+
+    pub fn static_value(&self, name: &str) -> i16 {
+        self.statics()[name]
+    }
+
+    pub fn stack_pointer(&self) -> u16 {
+        self.computer.ram[0]
+    }
+}
+
+/// Parses `(module name, source)` pairs.
+pub fn modules(sources: &[(&str, &str)]) -> Vec<VmModule> {
+    sources.iter().map(|(name, source)| parse(name, source).unwrap()).collect()
+}
+
+/// Translates the modules with `options`, assembles them and runs the program until it
+/// reaches a halt loop, such as `label END; goto END` in `Sys.init`.
+pub fn run(modules: &[VmModule], options: &Options) -> Run {
+    let translation = translate_with_options(modules, options).unwrap();
+    let (binary, symbols) = assembler::assemble_with_symbols(&translation.asm).unwrap();
+    let mut computer = Computer::new(&load_hack(&binary).unwrap());
+    assert!(computer.run(Some(MAX_CYCLES)), "no halt within {} cycles with {:?}", MAX_CYCLES, options);
+    Run { computer, symbols }
+}
@@ -0,0 +1,40 @@
+//! Runs `compare.tst`, the table of eq/gt/lt edge cases in `compare.vm` and their
+//! expected results in `compare.cmp`, on the translations of each comparison mode.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use vm_translator::{parse, translate_with_options, Options};
+
+// Copies the test files next to a fresh translation of `compare.vm`, in a directory of
+// its own so the modes can run in parallel.
+fn run_compare(mode: &str, options: &Options) -> Result<String, String> {
+    let source_directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let directory: PathBuf = std::env::temp_dir().join(format!("vm-translator-compare-{}-{}", mode, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    for file in ["compare.tst", "compare.cmp"] {
+        fs::copy(source_directory.join(file), directory.join(file)).unwrap();
+    }
+    let module = parse("compare", &fs::read_to_string(source_directory.join("compare.vm")).unwrap()).unwrap();
+    let translation = translate_with_options(&[module], options).unwrap();
+    fs::write(directory.join("compare.asm"), translation.asm).unwrap();
+    let result = cpu_emulator::run_test_script(&directory.join("compare.tst"));
+    fs::remove_dir_all(&directory).unwrap();
+    result
+}
+
+#[test]
+fn comparisons_match_the_table() {
+    run_compare("default", &Options::default()).unwrap();
+}
+
+#[test]
+fn shared_comparison_routines_match_the_table() {
+    run_compare("size", &Options { optimize_size: true, ..Options::default() }).unwrap();
+}
+
+#[test]
+fn unchecked_comparisons_fail_on_overflow() {
+    let error = run_compare("unchecked", &Options { unchecked_compare: true, ..Options::default() }).unwrap_err();
+    assert!(error.contains("Comparison failure"), "{}", error);
+}
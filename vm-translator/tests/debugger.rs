@@ -0,0 +1,110 @@
+mod common;
+
+use cpu_emulator::{load_hack, Computer, Debugger, Symbols};
+use vm_translator::{translate_with_options, Options};
+
+const SYS: &str = "\
+    function Sys.init 0\n\
+    push constant 3\n\
+    label CALL\n\
+    call Main.sum 1\n\
+    pop static 0\n\
+    label END\n\
+    goto END\n";
+
+// The sum of 1 to n, calling itself from a single place.
+const MAIN: &str = "\
+    function Main.sum 0\n\
+    push argument 0\n\
+    if-goto RECURSE\n\
+    push constant 0\n\
+    return\n\
+    label RECURSE\n\
+    push argument 0\n\
+    push argument 0\n\
+    push constant 1\n\
+    sub\n\
+    label CALL\n\
+    call Main.sum 1\n\
+    add\n\
+    return\n";
+
+// A debugger on the translated program, with the assembler's addresses of its labels.
+fn debugger(options: &Options) -> (Debugger, impl Fn(&str) -> u16) {
+    let asm = translate_with_options(&common::modules(&[("Sys", SYS), ("Main", MAIN)]), options).unwrap().asm;
+    let (binary, symbols) = assembler::assemble_with_symbols(&asm).unwrap();
+    let debugger = Debugger::new(Computer::new(&load_hack(&binary).unwrap()), Symbols::from_assembly(&asm).unwrap());
+    (debugger, move |label: &str| symbols[label])
+}
+
+fn top_of_stack(debugger: &Debugger) -> i16 {
+    let ram = &debugger.computer.ram;
+    ram[ram[0] as usize - 1] as i16
+}
+
+// The call sequence is inline by default and jumps to a shared routine with optimize_size.
+fn modes() -> [Options; 2] {
+    [Options::default(), Options { optimize_size: true, ..Options::default() }]
+}
+
+#[test]
+fn breakpoints_on_functions_stop_at_their_first_instruction() {
+    for options in &modes() {
+        let (mut debugger, address) = debugger(options);
+        let expected = format!("Breakpoint at {} (Main.sum)", address("Main.sum"));
+        assert_eq!(debugger.execute("break Main.sum").unwrap(), expected);
+        assert!(debugger.execute("c").unwrap().starts_with(&expected));
+        assert_eq!(debugger.computer.pc, address("Main.sum"));
+        assert_eq!(debugger.execute("b").unwrap(), expected);
+        assert_eq!(debugger.execute("b Main.missing").unwrap_err(), "Unknown ROM address or label: Main.missing");
+    }
+}
+
+#[test]
+fn next_steps_over_a_call_to_its_return_label() {
+    for options in &modes() {
+        let (mut debugger, address) = debugger(options);
+        debugger.execute("b Sys.init$CALL").unwrap();
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.computer.pc, address("Sys.init$CALL"));
+        debugger.execute("d").unwrap();
+
+        let output = debugger.execute("next").unwrap();
+        let return_label = address("Sys.init$$RETURN_LABEL0");
+        assert_eq!(debugger.computer.pc, return_label, "{:?}\n{}", options, output);
+        assert!(output.contains("(Sys.init$$RETURN_LABEL0)"), "{}", output);
+        assert_eq!(top_of_stack(&debugger), 6);
+    }
+}
+
+// From the recursive call site, `next` runs past the inner calls returning to the same
+// label and stops when the outer one returns.
+#[test]
+fn next_steps_over_a_recursive_call() {
+    for options in &modes() {
+        let (mut debugger, address) = debugger(options);
+        debugger.execute("b Main.sum$CALL").unwrap();
+        debugger.execute("c").unwrap();
+        debugger.execute("d").unwrap();
+        // sum(3) is about to call sum(2).
+        assert_eq!(top_of_stack(&debugger), 2);
+        let stack_pointer = debugger.computer.ram[0];
+
+        debugger.execute("next").unwrap();
+        assert_eq!(debugger.computer.pc, address("Main.sum$$RETURN_LABEL0"), "{:?}", options);
+        // The argument replaced by the result.
+        assert_eq!(debugger.computer.ram[0], stack_pointer);
+        assert_eq!(top_of_stack(&debugger), 3);
+    }
+}
+
+// Elsewhere `next` is a single step.
+#[test]
+fn next_outside_a_call_steps_once() {
+    let (mut debugger, address) = debugger(&Options::default());
+    debugger.execute("b Main.sum").unwrap();
+    debugger.execute("c").unwrap();
+    let cycles = debugger.computer.cycles;
+    debugger.execute("n").unwrap();
+    assert_eq!((debugger.computer.pc, debugger.computer.cycles), (address("Main.sum") + 1, cycles + 1));
+}
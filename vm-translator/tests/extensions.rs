@@ -0,0 +1,94 @@
+mod common;
+
+use vm_translator::Options;
+
+// Values around the edges: zero, ±1, the extremes and small operands of both signs.
+const VALUES: [i16; 11] = [0, 1, -1, 3, -3, 7, -7, 100, -100, i16::MAX, i16::MIN];
+const SHIFTS: [i16; 8] = [-16, -1, 0, 1, 3, 15, 16, 17];
+
+// Each extension as documented on the runtime.
+fn expected(operation: &str, x: i16, y: i16) -> i16 {
+    match operation {
+        "mult" => x.wrapping_mul(y),
+        "div" if y == 0 => 0,
+        "div" => x.wrapping_div(y),
+        "mod" if y == 0 => 0,
+        "mod" => x.wrapping_rem(y),
+        _ if y <= 0 => x,
+        _ if y >= 16 => 0,
+        "shl" => ((x as u16) << y) as i16,
+        "shr" => ((x as u16) >> y) as i16,
+        _ => unreachable!("not an extension: {}", operation),
+    }
+}
+
+// `push constant` only takes 0 to 32767.
+fn push(value: i16) -> String {
+    match value {
+        i16::MIN => String::from("push constant 32767\nneg\npush constant 1\nsub\n"),
+        _ if value < 0 => format!("push constant {}\nneg\n", -value),
+        _ => format!("push constant {}\n", value),
+    }
+}
+
+// Runs `x operation y` for every pair, in every code generation mode, storing each
+// result in its own static, and checks the results and that the stack is left as the
+// bootstrap made it.
+fn check(operation: &str, xs: &[i16], ys: &[i16]) {
+    let cases: Vec<(i16, i16)> = xs.iter().flat_map(|x| ys.iter().map(move |y| (*x, *y))).collect();
+    let mut source = String::from("function Sys.init 0\n");
+    for (index, (x, y)) in cases.iter().enumerate() {
+        source.push_str(&format!("{}{}{}\npop static {}\n", push(*x), push(*y), operation, index));
+    }
+    source.push_str("label END\ngoto END\n");
+    let modules = common::modules(&[("Sys", &source)]);
+    let extensions = Options { extensions: true, ..Options::default() };
+    let modes = [
+        ("default", extensions.clone()),
+        ("optimize_size", Options { optimize_size: true, ..extensions.clone() }),
+        ("cache_tos", Options { cache_tos: true, ..extensions.clone() }),
+        ("optimize_size and cache_tos", Options { optimize_size: true, cache_tos: true, ..extensions }),
+    ];
+    for (mode, options) in modes {
+        let run = common::run(&modules, &options);
+        for (index, (x, y)) in cases.iter().enumerate() {
+            let result = run.static_value(&format!("Sys.{}", index));
+            assert_eq!(result, expected(operation, *x, *y), "{} {} {} with {}", x, operation, y, mode);
+        }
+        // The bootstrap's frame for Sys.init.
+        assert_eq!(run.stack_pointer(), 261, "{} with {}", operation, mode);
+    }
+}
+
+#[test]
+fn mult_wraps_around() {
+    assert_eq!(expected("mult", i16::MIN, -1), i16::MIN);
+    check("mult", &VALUES, &VALUES);
+}
+
+#[test]
+fn div_rounds_towards_zero() {
+    assert_eq!(expected("div", i16::MIN, -1), i16::MIN);
+    assert_eq!(expected("div", -7, 0), 0);
+    check("div", &VALUES, &VALUES);
+}
+
+#[test]
+fn mod_has_the_sign_of_x() {
+    assert_eq!(expected("mod", -7, 3), -1);
+    assert_eq!(expected("mod", 7, -3), 1);
+    assert_eq!(expected("mod", 7, 0), 0);
+    check("mod", &VALUES, &VALUES);
+}
+
+#[test]
+fn shl_by_out_of_range_counts() {
+    assert_eq!(expected("shl", 1, 15), i16::MIN);
+    check("shl", &VALUES, &SHIFTS);
+}
+
+#[test]
+fn shr_fills_with_zeros() {
+    assert_eq!(expected("shr", -1, 15), 1);
+    check("shr", &VALUES, &SHIFTS);
+}
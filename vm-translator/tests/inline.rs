@@ -0,0 +1,31 @@
+mod common;
+
+use vm_translator::Options;
+
+// Inlining is only correct when the body hands back exactly one value, as `return`
+// keeps only the top of the stack; other functions must stay real calls.
+#[test]
+fn functions_leaving_other_than_one_value_are_not_inlined() {
+    let sys = "\
+        function Sys.init 0\n\
+        push constant 5\n\
+        call Sys.two 0\n\
+        call Sys.drop 1\n\
+        pop static 0\n\
+        pop static 1\n\
+        label END\n\
+        goto END\n\
+        function Sys.two 0\n\
+        push constant 1\n\
+        push constant 2\n\
+        return\n\
+        function Sys.drop 0\n\
+        pop static 2\n\
+        push constant 7\n\
+        return\n";
+    let modules = common::modules(&[("Sys", sys)]);
+    let expected = common::run(&modules, &Options::default());
+    let inlined = common::run(&modules, &Options { optimize: true, inline_limit: 8, ..Options::default() });
+    assert_eq!(inlined.stack_pointer(), expected.stack_pointer());
+    assert_eq!(inlined.statics(), expected.statics());
+}
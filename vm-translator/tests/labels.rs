@@ -0,0 +1,34 @@
+mod common;
+
+use vm_translator::{parse, Options};
+
+// A VM label named like a generated one must not replace it: `eq` makes up a label in
+// `Sys.init` too, and the assembler keeps the last definition of a label.
+#[test]
+fn vm_labels_do_not_collide_with_generated_ones() {
+    let source = "\
+        function Sys.init 0\n\
+        push constant 1\n\
+        push constant 1\n\
+        eq\n\
+        pop static 0\n\
+        goto SKIP\n\
+        label EQ0\n\
+        push constant 99\n\
+        pop static 1\n\
+        label SKIP\n\
+        label END\n\
+        goto END\n";
+    for options in [Options::default(), Options { optimize_size: true, ..Options::default() }] {
+        let run = common::run(&common::modules(&[("Sys", source)]), &options);
+        assert_eq!(run.static_value("Sys.0"), -1);
+        assert_eq!(run.static_value("Sys.1"), 0);
+    }
+}
+
+#[test]
+fn labels_containing_dollar_signs_are_rejected() {
+    assert!(parse("Main", "label $EQ0\n").is_err());
+    assert!(parse("Main", "goto A$B\n").is_err());
+    assert!(parse("Main", "if-goto A$B\n").is_err());
+}
@@ -0,0 +1,416 @@
+//! Every program is translated plainly and with each optimization, run on the emulator
+//! and must end with the same statics and stack pointer.
+
+mod common;
+
+use vm_translator::{optimize, parse, read_bytecode, translate_with_options, write_bytecode, Options, VmModule};
+
+// Folding, including results that need the `i16::MIN` form, wrap-around and the
+// operations that are dropped: `neg; neg`, `not; not`, `x + 0`, `x & -1`, ...
+const FOLDING: &str = "\
+    function Sys.init 0\n\
+    push constant 7\n\
+    push constant 5\n\
+    sub\n\
+    pop static 0\n\
+    push constant 0\n\
+    push constant 32767\n\
+    sub\n\
+    push constant 1\n\
+    sub\n\
+    pop static 1\n\
+    push constant 32767\n\
+    neg\n\
+    push constant 1\n\
+    sub\n\
+    neg\n\
+    pop static 2\n\
+    push constant 3\n\
+    push constant 3\n\
+    eq\n\
+    pop static 3\n\
+    push constant 2\n\
+    push constant 3\n\
+    gt\n\
+    not\n\
+    pop static 4\n\
+    push static 0\n\
+    neg\n\
+    neg\n\
+    pop static 5\n\
+    push static 0\n\
+    not\n\
+    not\n\
+    push constant 0\n\
+    add\n\
+    pop static 6\n\
+    push static 1\n\
+    push constant 0\n\
+    sub\n\
+    push constant 0\n\
+    not\n\
+    and\n\
+    push constant 0\n\
+    or\n\
+    pop static 7\n\
+    push constant 20000\n\
+    push constant 20000\n\
+    add\n\
+    pop static 8\n\
+    push constant 12\n\
+    push static 0\n\
+    lt\n\
+    pop static 9\n\
+    push static 1\n\
+    push constant 0\n\
+    push constant 32767\n\
+    sub\n\
+    push constant 1\n\
+    sub\n\
+    eq\n\
+    pop static 10\n\
+    push constant 5\n\
+    neg\n\
+    neg\n\
+    neg\n\
+    pop static 11\n\
+    label END\n\
+    goto END\n";
+
+// The sequences `optimize` fuses into specialized code: moves between segments,
+// increments, constant operands and pointer segments.
+const FUSION: &str = "\
+    function Sys.init 2\n\
+    push constant 10\n\
+    pop local 0\n\
+    push local 0\n\
+    push constant 1\n\
+    add\n\
+    pop local 1\n\
+    push local 1\n\
+    push constant 1\n\
+    sub\n\
+    pop static 0\n\
+    push local 0\n\
+    push local 1\n\
+    add\n\
+    pop static 1\n\
+    push constant 3000\n\
+    pop pointer 1\n\
+    push local 1\n\
+    pop that 1\n\
+    push constant 0\n\
+    pop that 0\n\
+    push that 1\n\
+    push constant 5\n\
+    sub\n\
+    pop static 2\n\
+    push constant 4000\n\
+    pop pointer 0\n\
+    push that 1\n\
+    pop this 2\n\
+    push this 2\n\
+    push constant 1\n\
+    neg\n\
+    add\n\
+    pop static 3\n\
+    push local 0\n\
+    push constant 0\n\
+    eq\n\
+    pop static 4\n\
+    push pointer 0\n\
+    push pointer 1\n\
+    sub\n\
+    pop static 5\n\
+    push local 1\n\
+    pop temp 3\n\
+    push temp 3\n\
+    push local 0\n\
+    and\n\
+    pop static 6\n\
+    label END\n\
+    goto END\n";
+
+// `Sys.unused` and `Main.unused` only call each other; `Main.live` is reached from
+// `Sys.init` through another module.
+const DEAD: &str = "\
+    function Sys.init 0\n\
+    call Main.live 0\n\
+    pop static 0\n\
+    label END\n\
+    goto END\n\
+    function Sys.unused 0\n\
+    call Main.unused 0\n\
+    return\n";
+
+const DEAD_MAIN: &str = "\
+    function Main.live 0\n\
+    push constant 42\n\
+    pop static 0\n\
+    push static 0\n\
+    return\n\
+    function Main.unused 0\n\
+    call Sys.unused 0\n\
+    return\n";
+
+// Calls in tail position, recursive calls that are not, and loops with `if-goto`.
+const CALLS: &str = "\
+    function Sys.init 0\n\
+    push constant 10\n\
+    push constant 0\n\
+    call Sys.sum 2\n\
+    pop static 0\n\
+    push constant 6\n\
+    call Sys.factorial 1\n\
+    pop static 1\n\
+    call Main.run 0\n\
+    pop static 2\n\
+    label END\n\
+    goto END\n\
+    function Sys.sum 0\n\
+    push argument 0\n\
+    if-goto RECURSE\n\
+    push argument 1\n\
+    return\n\
+    label RECURSE\n\
+    push argument 0\n\
+    push constant 1\n\
+    sub\n\
+    push argument 1\n\
+    push argument 0\n\
+    add\n\
+    call Sys.sum 2\n\
+    return\n\
+    function Sys.factorial 0\n\
+    push argument 0\n\
+    push constant 1\n\
+    gt\n\
+    if-goto RECURSE\n\
+    push constant 1\n\
+    return\n\
+    label RECURSE\n\
+    push argument 0\n\
+    push argument 0\n\
+    push constant 1\n\
+    sub\n\
+    call Sys.factorial 1\n\
+    call Sys.multiply 2\n\
+    return\n\
+    function Sys.multiply 1\n\
+    label LOOP\n\
+    push argument 1\n\
+    push constant 0\n\
+    eq\n\
+    if-goto DONE\n\
+    push local 0\n\
+    push argument 0\n\
+    add\n\
+    pop local 0\n\
+    push argument 1\n\
+    push constant 1\n\
+    sub\n\
+    pop argument 1\n\
+    goto LOOP\n\
+    label DONE\n\
+    push local 0\n\
+    return\n";
+
+const MAIN: &str = "\
+    function Main.run 0\n\
+    push constant 5\n\
+    pop static 0\n\
+    label LOOP\n\
+    push static 0\n\
+    push constant 0\n\
+    gt\n\
+    not\n\
+    if-goto END\n\
+    push static 1\n\
+    push static 0\n\
+    add\n\
+    pop static 1\n\
+    push static 0\n\
+    push constant 1\n\
+    sub\n\
+    pop static 0\n\
+    goto LOOP\n\
+    label END\n\
+    push static 1\n\
+    return\n";
+
+// Leaf functions to inline: with arguments and locals, writing THAT, using statics,
+// called from a function keeping its own values in temp.
+const LEAVES: &str = "\
+    function Sys.init 0\n\
+    push constant 9\n\
+    pop temp 0\n\
+    push constant 5000\n\
+    pop pointer 1\n\
+    push constant 4\n\
+    push constant 5\n\
+    call Sys.addOne 2\n\
+    pop static 0\n\
+    push temp 0\n\
+    pop static 1\n\
+    push constant 6000\n\
+    call Sys.setThat 1\n\
+    pop static 2\n\
+    push pointer 1\n\
+    pop static 3\n\
+    call Sys.counter 0\n\
+    pop temp 1\n\
+    call Sys.counter 0\n\
+    pop static 4\n\
+    push temp 0\n\
+    push temp 1\n\
+    add\n\
+    pop static 5\n\
+    label END\n\
+    goto END\n\
+    function Sys.addOne 1\n\
+    push argument 0\n\
+    push argument 1\n\
+    add\n\
+    pop local 0\n\
+    push local 0\n\
+    push constant 1\n\
+    add\n\
+    return\n\
+    function Sys.setThat 0\n\
+    push argument 0\n\
+    pop pointer 1\n\
+    push constant 77\n\
+    pop that 0\n\
+    push that 0\n\
+    return\n\
+    function Sys.counter 0\n\
+    push static 9\n\
+    push constant 1\n\
+    add\n\
+    pop static 9\n\
+    push static 9\n\
+    return\n";
+
+fn programs() -> Vec<(&'static str, Vec<VmModule>)> {
+    vec![
+        ("folding", common::modules(&[("Sys", FOLDING)])),
+        ("fusion", common::modules(&[("Sys", FUSION)])),
+        ("dead", common::modules(&[("Sys", DEAD), ("Main", DEAD_MAIN)])),
+        ("calls", common::modules(&[("Sys", CALLS), ("Main", MAIN)])),
+        ("leaves", common::modules(&[("Sys", LEAVES)])),
+    ]
+}
+
+fn optimizations() -> Vec<(&'static str, Options)> {
+    let default = Options::default();
+    vec![
+        ("optimize", Options { optimize: true, ..default.clone() }),
+        ("optimize_size", Options { optimize: true, optimize_size: true, ..default.clone() }),
+        ("cache_tos", Options { cache_tos: true, ..default.clone() }),
+        ("optimize_cache_tos", Options { optimize: true, cache_tos: true, ..default.clone() }),
+        ("eliminate_dead_functions", Options { eliminate_dead_functions: true, ..default.clone() }),
+        ("tail_calls", Options { tail_calls: true, ..default.clone() }),
+        ("inline", Options { optimize: true, inline_limit: 8, ..default.clone() }),
+        ("all", Options {
+            optimize: true,
+            cache_tos: true,
+            tail_calls: true,
+            inline_limit: 8,
+            eliminate_dead_functions: true,
+            ..default.clone()
+        }),
+        ("all_size", Options {
+            optimize: true,
+            optimize_size: true,
+            tail_calls: true,
+            inline_limit: 8,
+            eliminate_dead_functions: true,
+            ..default
+        }),
+    ]
+}
+
+#[test]
+fn optimizations_keep_the_results() {
+    for (program, modules) in programs() {
+        let expected = common::run(&modules, &Options::default());
+        for (optimization, options) in optimizations() {
+            let run = common::run(&modules, &options);
+            assert_eq!(run.statics(), expected.statics(), "{} with {}", program, optimization);
+            assert_eq!(run.stack_pointer(), expected.stack_pointer(), "{} with {}", program, optimization);
+        }
+    }
+}
+
+#[test]
+fn folded_results_are_as_computed_by_hand() {
+    let folding = common::run(&common::modules(&[("Sys", FOLDING)]), &Options::default());
+    let expected = [2, i16::MIN, i16::MIN, -1, -1, 2, 2, i16::MIN, -25536, 0, -1, -5];
+    for (index, value) in expected.iter().enumerate() {
+        assert_eq!(folding.static_value(&format!("Sys.{}", index)), *value, "Sys.{}", index);
+    }
+}
+
+#[test]
+fn folding_emits_canonical_constants() {
+    let folded = |source: &str| {
+        let module = optimize(&parse("Main", source).unwrap());
+        module.commands.iter().map(|command| command.command.to_string()).collect::<Vec<_>>()
+    };
+    // -32768 cannot be written as `push constant 32768; neg`.
+    assert_eq!(
+        folded("push constant 0\npush constant 32767\nsub\npush constant 1\nsub\n"),
+        ["push constant 32767", "neg", "push constant 1", "sub"]
+    );
+    assert_eq!(folded("push constant 3\npush constant 5\nsub\n"), ["push constant 2", "neg"]);
+    assert_eq!(folded("push static 0\nneg\nneg\npop static 1\n"), ["push static 0", "pop static 1"]);
+    assert_eq!(folded("push static 0\nnot\nnot\nnot\n"), ["push static 0", "not"]);
+    assert_eq!(folded("push static 0\npush constant 0\nadd\n"), ["push static 0"]);
+}
+
+#[test]
+fn only_unreachable_functions_are_removed() {
+    let modules = common::modules(&[("Sys", DEAD), ("Main", DEAD_MAIN)]);
+    let options = Options { eliminate_dead_functions: true, ..Options::default() };
+    let asm = translate_with_options(&modules, &options).unwrap().asm;
+    assert!(asm.contains("(Main.live)"));
+    assert!(!asm.contains("(Sys.unused)"));
+    assert!(!asm.contains("(Main.unused)"));
+    assert_eq!(common::run(&modules, &options).static_value("Main.0"), 42);
+}
+
+#[test]
+fn calls_return_the_values_computed_by_hand() {
+    let calls = common::run(&common::modules(&[("Sys", CALLS), ("Main", MAIN)]), &Options::default());
+    assert_eq!(calls.static_value("Sys.0"), 55);
+    assert_eq!(calls.static_value("Sys.1"), 720);
+    assert_eq!(calls.static_value("Sys.2"), 15);
+}
+
+#[test]
+fn leaves_return_the_values_computed_by_hand() {
+    let leaves = common::run(&common::modules(&[("Sys", LEAVES)]), &Options::default());
+    let expected = [10, 9, 77, 5000, 2, 10];
+    for (index, value) in expected.iter().enumerate() {
+        assert_eq!(leaves.static_value(&format!("Sys.{}", index)), *value, "Sys.{}", index);
+    }
+}
+
+#[test]
+fn bytecode_round_trip_keeps_the_program() {
+    for (program, modules) in programs() {
+        let bytes = write_bytecode(&modules);
+        let read = read_bytecode("test.vmb", &bytes).unwrap();
+        let commands = |modules: &[VmModule]| {
+            modules
+                .iter()
+                .map(|module| (module.name.clone(), module.commands.iter().map(|command| command.command.clone()).collect()))
+                .collect::<Vec<(String, Vec<_>)>>()
+        };
+        assert_eq!(commands(&read), commands(&modules), "{}", program);
+        let asm = |modules: &[VmModule]| translate_with_options(modules, &Options::default()).unwrap().asm;
+        assert_eq!(asm(&read), asm(&modules), "{}", program);
+        let statics = |modules: &[VmModule]| common::run(modules, &Options::default()).statics();
+        assert_eq!(statics(&read), statics(&modules), "{}", program);
+    }
+}
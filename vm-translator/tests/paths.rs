@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::PathBuf;
+
+use vm_translator::{expand_inputs, output_path};
+
+// A fresh directory holding empty files at the given relative paths.
+fn project(name: &str, files: &[&str]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("vm-translator-paths-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    for file in files {
+        fs::create_dir_all(directory.join(file).parent().unwrap()).unwrap();
+        fs::write(directory.join(file), "").unwrap();
+    }
+    directory
+}
+
+// The default or requested output for the inputs, as the command line works it out.
+fn output(inputs: &[PathBuf], requested: Option<&str>) -> Result<Option<PathBuf>, String> {
+    let filepaths = expand_inputs(inputs).unwrap();
+    output_path(inputs, &filepaths, requested, "asm")
+}
+
+#[test]
+fn a_file_gets_its_extension_replaced() {
+    let directory = project("file", &["Foo.vm"]);
+    assert_eq!(output(&[directory.join("Foo.vm")], None), Ok(Some(directory.join("Foo.asm"))));
+    let filepaths = [directory.join("Foo.vm")];
+    assert_eq!(output_path(&filepaths, &filepaths, None, "hack"), Ok(Some(directory.join("Foo.hack"))));
+}
+
+#[test]
+fn only_the_extension_of_a_file_in_a_dotted_directory_is_replaced() {
+    let directory = project("dotted-file", &["a.b/Foo.vm"]);
+    assert_eq!(output(&[directory.join("a.b/Foo.vm")], None), Ok(Some(directory.join("a.b/Foo.asm"))));
+}
+
+#[test]
+fn a_directory_is_named_after_itself() {
+    let directory = project("directory", &["Prog/Main.vm", "Prog/Sys.vm", "Prog/notes.txt"]);
+    let inputs = [directory.join("Prog")];
+    assert_eq!(
+        expand_inputs(&inputs),
+        Ok(vec![directory.join("Prog/Main.vm"), directory.join("Prog/Sys.vm")])
+    );
+    assert_eq!(output(&inputs, None), Ok(Some(directory.join("Prog/Prog.asm"))));
+    // A trailing slash names the same directory.
+    let input = PathBuf::from(format!("{}/", directory.join("Prog").display()));
+    assert_eq!(output(&[input], None), Ok(Some(directory.join("Prog/Prog.asm"))));
+}
+
+#[test]
+fn a_dotted_directory_keeps_its_whole_name() {
+    let directory = project("dotted-directory", &["my.vm.tests/Main.vm"]);
+    let inputs = [directory.join("my.vm.tests")];
+    assert_eq!(output(&inputs, None), Ok(Some(directory.join("my.vm.tests/my.vm.tests.asm"))));
+}
+
+#[test]
+fn several_inputs_need_an_output_file() {
+    let directory = project("several", &["Main.vm", "Sys.vm"]);
+    let inputs = [directory.join("Main.vm"), directory.join("Sys.vm")];
+    assert!(output(&inputs, None).unwrap_err().contains("-o"));
+    let requested = directory.join("Out.asm");
+    assert_eq!(output(&inputs, requested.to_str()), Ok(Some(requested)));
+    assert_eq!(output(&inputs, Some("-")), Ok(None));
+}
+
+#[test]
+fn an_input_is_never_overwritten() {
+    let directory = project("overwrite", &["Main.vm", "Sys.vm"]);
+    let inputs = [directory.join("Main.vm"), directory.join("Sys.vm")];
+    let error = output(&inputs, directory.join("Sys.vm").to_str()).unwrap_err();
+    assert!(error.contains("Refusing to overwrite"), "{}", error);
+    // Also when written another way.
+    let other_way = directory.join(".").join("Main.vm");
+    assert!(output(&inputs, other_way.to_str()).is_err());
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use vm_translator::{Options, PROFILE_BASE};
+
+// `Sys.getter` would be inlined with `optimize`, which would leave its counter at 0.
+#[test]
+fn profiling_counts_calls_of_inlinable_functions() {
+    let sys = "\
+        function Sys.init 0\n\
+        call Sys.getter 0\n\
+        pop static 0\n\
+        call Sys.getter 0\n\
+        pop static 0\n\
+        label END\n\
+        goto END\n\
+        function Sys.getter 0\n\
+        push constant 3\n\
+        return\n";
+    let options = Options { optimize: true, inline_limit: 8, profile: true, ..Options::default() };
+    let run = common::run(&common::modules(&[("Sys", sys)]), &options);
+    assert_eq!(run.computer.ram[PROFILE_BASE as usize], 1);
+    assert_eq!(run.computer.ram[PROFILE_BASE as usize + 1], 2);
+}
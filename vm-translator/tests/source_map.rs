@@ -0,0 +1,51 @@
+use vm_translator::{parse, translate_with_options, Options};
+
+// Two modules, so lines map to the right file, with a blank line and a comment that
+// keep the VM line numbers apart from the command count.
+#[test]
+fn emitted_lines_map_back_to_their_vm_lines() {
+    let sys = "\
+        function Sys.init 0\n\
+        push constant 1\n\
+        \n\
+        label LOOP\n\
+        goto LOOP\n";
+    let main = "\
+        // Nothing calls this.\n\
+        function Main.main 0\n\
+        push constant 2\n\
+        return\n";
+    let modules = [parse("Sys", sys).unwrap(), parse("Main", main).unwrap()];
+    let translation = translate_with_options(&modules, &Options { comments: true, ..Options::default() }).unwrap();
+    let map = translation.format_source_map(&modules);
+    let lines: Vec<(&str, &str)> = translation.asm.lines()
+        .zip(map.lines())
+        .map(|(asm, location)| (asm, location.split_once(' ').unwrap().1))
+        .collect();
+    assert_eq!(map.lines().count(), translation.asm.lines().count());
+    assert!(map.lines().enumerate().all(|(index, line)| line.starts_with(&format!("{} ", index + 1))));
+
+    let location = |asm: &str| lines.iter().find(|(line, _)| *line == asm).map(|(_, location)| *location);
+    assert_eq!(lines[0], ("// bootstrap", "-"));
+    assert_eq!(location("(Bootstrap$$RETURN_LABEL0)"), Some("-"));
+    assert_eq!(location("// Sys.vm:1: function Sys.init 0"), Some("Sys.vm:1"));
+    assert_eq!(location("(Sys.init)"), Some("Sys.vm:1"));
+    assert_eq!(location("// Sys.vm:4: label LOOP"), Some("Sys.vm:4"));
+    assert_eq!(location("(Sys.init$LOOP)"), Some("Sys.vm:4"));
+    assert_eq!(location("@Sys.init$LOOP"), Some("Sys.vm:5"));
+    assert_eq!(location("(Main.main)"), Some("Main.vm:2"));
+    assert_eq!(location("// Main.vm:3: push constant 2"), Some("Main.vm:3"));
+
+    // Every command's comment maps to the line it names, and so does its code up to the
+    // next comment.
+    let mut current = None;
+    for (asm, location) in &lines {
+        if let Some(comment) = asm.strip_prefix("// ").filter(|comment| comment.contains(".vm:")) {
+            let (file, rest) = comment.split_once(':').unwrap();
+            current = Some(format!("{}:{}", file, rest.split_once(':').unwrap().0));
+        }
+        if let Some(current) = &current {
+            assert_eq!(location, current, "{}", asm);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use vm_translator::{Options, VmModule, DEFAULT_STACK_LIMIT, STACK_ERROR_ADDRESS, STACK_ERROR_CALL, STACK_ERROR_FUNCTION};
+
+fn modes() -> [Options; 2] {
+    let guarded = Options { stack_limit: Some(DEFAULT_STACK_LIMIT), ..Options::default() };
+    [guarded.clone(), Options { optimize_size: true, ..guarded }]
+}
+
+// Sys.1 is only set if Sys.init gets past the call.
+fn program(callee: &str) -> Vec<VmModule> {
+    let sys = "\
+        function Sys.init 0\n\
+        push constant 100\n\
+        call Main.f 1\n\
+        pop static 0\n\
+        push constant 1\n\
+        pop static 1\n\
+        label END\n\
+        goto END\n";
+    common::modules(&[("Sys", sys), ("Main", callee)])
+}
+
+#[test]
+fn unbounded_recursion_stops_at_a_call() {
+    let main = "\
+        function Main.f 0\n\
+        push argument 0\n\
+        call Main.f 1\n\
+        return\n";
+    for options in modes() {
+        let run = common::run(&program(main), &options);
+        assert_eq!(run.computer.ram[STACK_ERROR_ADDRESS as usize], STACK_ERROR_CALL);
+        assert_eq!(run.static_value("Sys.1"), 0);
+        assert!(run.stack_pointer() > DEFAULT_STACK_LIMIT);
+    }
+}
+
+#[test]
+fn locals_past_the_limit_stop_at_function_entry() {
+    let main = "\
+        function Main.f 2000\n\
+        push constant 0\n\
+        return\n";
+    for options in modes() {
+        let run = common::run(&program(main), &options);
+        assert_eq!(run.computer.ram[STACK_ERROR_ADDRESS as usize], STACK_ERROR_FUNCTION);
+        assert_eq!(run.static_value("Sys.1"), 0);
+    }
+}
+
+#[test]
+fn deep_bounded_recursion_runs_to_the_end() {
+    // Sums 100 + 99 + ... + 1, 100 calls deep, about 900 words of stack.
+    let main = "\
+        function Main.f 1\n\
+        push argument 0\n\
+        pop local 0\n\
+        push local 0\n\
+        if-goto RECURSE\n\
+        push constant 0\n\
+        return\n\
+        label RECURSE\n\
+        push local 0\n\
+        push local 0\n\
+        push constant 1\n\
+        sub\n\
+        call Main.f 1\n\
+        add\n\
+        return\n";
+    for options in modes() {
+        let run = common::run(&program(main), &options);
+        assert_eq!(run.computer.ram[STACK_ERROR_ADDRESS as usize], 0);
+        assert_eq!(run.static_value("Sys.0"), 5050);
+        assert_eq!(run.static_value("Sys.1"), 1);
+    }
+}